@@ -17,151 +17,83 @@
 
 //! Argument processing
 
-use std::str::FromStr;
-use std::fmt::Debug;
-use std::ops::AddAssign;
-use std::cmp::Ordering;
+use std::fmt::Display;
 use std::process;
+use std::str::FromStr;
 
-use {ToolArgs, NN, RR};
-use tools::{Tool, DirectCalcTool, SimStructureTool, FullSimTool, SimResult};
-use quorum::{SimpleQuorum, AgeQuorum};
-use attack::{UntargettedAttack, SimpleTargettedAttack};
-
-
-pub trait DefaultStep<T> {
-    // Return a default step.
-    //
-    // The value `x` is passed so that RelOrAbs can see whether it's being
-    // used in relative or absolute form.
-    fn default_step(x: T) -> T;
-}
-
-impl DefaultStep<NN> for NN {
-    fn default_step(_: NN) -> NN {
-        1
-    }
-}
-
-impl DefaultStep<RR> for RR {
-    fn default_step(_: RR) -> RR {
-        1.0
-    }
-}
-
-pub enum SamplePoints<T> {
-    Range(T, T, Option<T>), // start, stop, optional step
-    List(Vec<T>),
-    Number(T),
+use clap::{ArgMatches, Error, ErrorKind};
+
+use routing_sims::NN;
+use routing_sims::params::{SimParams, SimType, AttackType, QuorumAlg, SweepSpec};
+use routing_sims::tools::Repetitions;
+use routing_sims::output::Format;
+use routing_sims::experiment::load_experiment;
+use routing_sims::arrival::Arrival;
+use routing_sims::schedule::Schedule;
+
+
+/// Options controlling a whole run (as opposed to parameters of individual simulations).
+pub struct RunOptions {
+    /// Master seed, if given (on the command line or in an experiment file)
+    pub seed: Option<NN>,
+    /// Format of results
+    pub format: Format,
+    /// File to write results to (stdout if `None`)
+    pub output: Option<String>,
+    /// If set, run a single simulation and write its state after each step to this file
+    pub trace: Option<String>,
+    /// Append to the output file, skipping parameter sets which already have results
+    pub resume: bool,
 }
 
-impl<T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T>> SamplePoints<T> {
-    fn iter(&self) -> SamplePointsIterator<T> {
-        SamplePointsIterator {
-            iterable: self,
-            i: 0,
-            prev: None,
-        }
-    }
+// Report an invalid argument and exit.
+fn fail(msg: &str) -> ! {
+    Error::with_description(msg, ErrorKind::InvalidValue).exit()
 }
 
-impl<T: FromStr> FromStr for SamplePoints<T>
-    where <T as FromStr>::Err: Debug
+// Parse the value of the option called `name` (`flag` on the command line), if given.
+fn parse_opt<T>(matches: &ArgMatches, name: &str, flag: &str) -> Option<T>
+    where T: FromStr,
+          <T as FromStr>::Err: Display
 {
-    type Err = ();  // we just panic!
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.contains('-') {
-            // We have a range; check for a step:
-            let (first, step) = if s.contains(':') {
-                let mut parts = s.split(':');
-                let first = parts.next().expect("split half");
-                let second = parts.next().expect("split half");
-                if parts.next() != None {
-                    panic!("expected 'start-stop:step', found {}", s);
-                }
-                (first, Some(second.parse().expect("parse")))
-            } else {
-                (s, None)
-            };
-            let mut parts = first.split('-');
-            let start = parts.next().expect("split half").parse().expect("parse");
-            let stop = match parts.next() {
-                    Some(part) => part,
-                    None => panic!("expected 'start-stop:step', found {}", s),
-                }
-                .parse()
-                .expect("parse");
-            if parts.next() != None {
-                panic!("expected 'start-stop:step', found {}", s);
-            }
-            Ok(SamplePoints::Range(start, stop, step))
-        } else if s.contains(',') {
-            // We have a list
-            let parts = s.split(',');
-            Ok(SamplePoints::List(parts.map(|p| p.parse().expect("parse")).collect()))
-        } else {
-            // Presumably we have a single number
-            Ok(SamplePoints::Number(s.parse().expect("parse")))
-        }
-    }
+    matches.value_of(name)
+        .map(|s| s.parse().unwrap_or_else(|e| fail(&format!("{} {}: {}", flag, s, e))))
 }
 
-struct SamplePointsIterator<'a, T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T> + 'a> {
-    iterable: &'a SamplePoints<T>,
-    i: usize,
-    prev: Option<T>,
-}
-
-impl<'a, T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T> + 'a> Iterator
-        for SamplePointsIterator<'a, T>
-{
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let i = self.i;
-        match self.iterable {
-            &SamplePoints::Range(start, stop, step) => {
-                match self.prev {
-                    None => {
-                        self.prev = Some(start);
-                        self.prev
-                    },
-                    Some(mut x) => {
-                        let step = step.unwrap_or(T::default_step(start));
-                        x += step;
-                        self.prev = Some(x);
-                        if x > stop {
-                            None
-                        } else {
-                            Some(x)
-                        }
-                    },
-                }
-            },
-            &SamplePoints::List(ref v) => {
-                if i >= v.len() {
-                    None
-                } else {
-                    self.i = i + 1;
-                    Some(v[i])
-                }
-            },
-            &SamplePoints::Number(n) => {
-                if i > 0 {
-                    None
-                } else {
-                    self.i = 1;
-                    Some(n)
-                }
-            },
-        }
-    }
-}
+// Options setting simulation parameters, which experiment files set instead: (name, flag)
+const SWEEP_ARGS: &[(&str, &str)] = &[("tool", "-t"),
+                                      ("nodes", "-n"),
+                                      ("attacking", "-a"),
+                                      ("arrival", "--arrival"),
+                                      ("maxjoin", "-j"),
+                                      ("backjoin", "-b"),
+                                      ("leavegood", "-l"),
+                                      ("backjoinsched", "--backjoinsched"),
+                                      ("leavesched", "--leavesched"),
+                                      ("group", "-g"),
+                                      ("quorum", "-q"),
+                                      ("agequorum", "--agequorum"),
+                                      ("prooftime", "--prooftime"),
+                                      ("maxdays", "-d"),
+                                      ("repetitions", "-p"),
+                                      ("precision", "--precision"),
+                                      ("maxtrials", "--maxtrials"),
+                                      ("maxtime", "--maxtime"),
+                                      ("quorum_alg", "-Q"),
+                                      ("agecap", "--agecap"),
+                                      ("elders", "--elders"),
+                                      ("strategy", "-S"),
+                                      ("attackage", "--attackage"),
+                                      ("agers", "--agers"),
+                                      ("floodpower", "--floodpower"),
+                                      ("resetcost", "--resetcost"),
+                                      ("budget", "--budget")];
 
 pub struct ArgProc {}
 
 impl ArgProc {
-    /// Return (repetitions, vec[sim params])
-    pub fn make_sim_params() -> (u32, Vec<SimParams>) {
+    /// Return (run options, vec[sim params])
+    pub fn make_sim_params() -> (RunOptions, Vec<SimParams>) {
         let matches = clap_app!(routing_sims =>
             (version: "0.1")
             (about: "Calculates vulnerabilities of networks via simulation.\
@@ -183,6 +115,11 @@ impl ArgProc {
             (@arg attacking: -a --attacking [RANGE] "Number of malicious nodes added in attack. \
                     Either an absolute number (e.g. 50) or a percentage of the number of initial \
                     nodes.")
+            (@arg arrival: --arrival [PROFILES] "How malicious nodes arrive during the attack: \
+                    'once' (all at the start; default), 'rate:R' (R nodes per day), 'ramp:D' \
+                    (linearly increasing rate, all arrived after D days) or 'file:PATH' (step \
+                    schedule of 'day, weight' lines). Several profiles may be given, separated \
+                    by commas.")
             (@arg maxjoin: -j --maxjoin [RANGE] "Maximum rate at which new nodes join the network \
                     (nodes per day); can be a percentage of initial nodes.")
             (@arg backjoin: -b --backjoin [RANGE] "Background joining rate of good nodes \
@@ -191,33 +128,115 @@ impl ArgProc {
                     node leaving each day); can be a percentage (expected number per 100 per \
                     day). Nodes which leave are replaced with new nodes to maintain the target \
                     number. Leaving happens randomly.")
+            (@arg backjoinsched: --backjoinsched [SCHEDULE] "Factor applied to the background \
+                    joining rate (-b) over the days of an attack: 'const' (default), \
+                    'linear:DAY=FACTOR,...' (piecewise-linear), 'sine:A:P' (1 + A sin(2 pi \
+                    day / P)) or 'file:PATH' (piecewise-linear from 'day, factor' lines).")
+            (@arg leavesched: --leavesched [SCHEDULE] "Factor applied to the leave rate of \
+                    good nodes (-l) over the days of an attack; see --backjoinsched.")
             (@arg group: -g --group [RANGE] "Minimum group size, e.g. 10-20.")
             (@arg quorum: -q --quorum [RANGE] "Quorum size as a proportion of group size, \
                     e.g. 0.5-0.7:0.1.")
+            (@arg agequorum: --agequorum [RANGE] "Quorum as a proportion of group age (or of \
+                    weighted age), used by -Q age, age_linear, age_exp, age_capped and age_sqrt \
+                    (thus weighted), e.g. 0.6-0.7:0.05. Default: the same as -q.")
             (@arg prooftime: --prooftime [RANGE] "Time taken to complete resource proof (days). \
                     Default is 1.")
             (@arg maxdays: -d --maxdays [RANGE] "Maximum length of an attack before giving up \
                     (days).")
             (@arg repetitions: -p --repetitions [NUM] "Number of times to repeat a true/false \
-                    simulation to calculate an attack success probability.")
-            (@arg quorum_alg: -Q --quorumalg "Quorum algorithm: 'simple' group proportion, \
-                    'age' (age and group proportions), 'all' (run both)")
-            (@arg strategy: -S --strategy "Attack targetting strategy: 'none', \
-                    'simple' (naive) targetting, 'all'")
+                    simulation to calculate an attack success probability. With --precision, \
+                    the number of repetitions in each batch.")
+            (@arg precision: --precision [WIDTH] "Repeat simulations in batches until the 95% \
+                    confidence intervals of both probabilities are no wider than WIDTH (e.g. \
+                    0.05), or until --maxtrials or --maxtime is reached. The number of trials \
+                    used is reported for each parameter set.")
+            (@arg maxtrials: --maxtrials [NUM] "Maximum number of repetitions per parameter \
+                    set; requires --precision. Default: 100000.")
+            (@arg maxtime: --maxtime [SECS] "Maximum time (seconds) spent per parameter set; \
+                    requires --precision. Note that this makes results depend on machine speed.")
+            (@arg seed: -s --seed [NUM] "Seed for the random number generators. Runs with the \
+                    same seed and parameters give identical results. Default: chosen randomly \
+                    (and printed with the results).")
+            (@arg format: -f --format [FORMAT] "Output format: 'table' (default; for reading), \
+                    'csv' or 'json' (one object per line). All formats include every parameter \
+                    and derived simulation argument alongside the results.")
+            (@arg output: -o --output [FILE] "Write results to FILE instead of standard output.")
+            (@arg resume: --resume "Append results to the --output file, skipping parameter \
+                    sets for which it already has results (e.g. to continue an interrupted \
+                    run). Requires CSV or JSON format. Unless --seed is given, the seed is \
+                    taken from the file.")
+            (@arg trace: --trace [FILE] "Run a single repetition of the full simulation and \
+                    write the state of the network after each step to FILE (in the format given \
+                    by --format). Requires a single parameter set.")
+            (@arg experiment: -e --experiment [FILE] "Read named scenarios (parameter sweeps) from \
+                    an experiment file (TOML, or JSON if FILE ends with .json) instead of from \
+                    the options above, which cannot be combined with it. The seed given in the \
+                    file is used unless --seed is given. See the documentation of the experiment \
+                    module for the format.")
+            (@arg quorum_alg: -Q --quorumalg [ALG] "Quorum algorithm: 'simple' group proportion, \
+                    'age' (age and group proportions), 'elder' (proportion of the oldest nodes; \
+                    see --elders), 'age_linear', 'age_exp', 'age_capped' or 'age_sqrt' (group \
+                    proportion and proportion of summed weights, where a node's weight is its \
+                    age, 2^age, its age capped at --agecap or sqrt(age)), 'weighted' (all four \
+                    weightings), 'bft' (malicious nodes vote on both sides of a conflict; \
+                    compromise is a safety break and disruption a liveness break), 'all' (run \
+                    each)")
+            (@arg agecap: --agecap [AGE] "Maximum age counted with -Q age_capped. Default: 4.")
+            (@arg elders: --elders [RANGE] "Number of oldest nodes in each group which vote, \
+                    with -Q elder. Default: 7.")
+            (@arg strategy: -S --strategy [STRATEGY] "Attack targetting strategy: 'none', \
+                    'simple' (naive) targetting, 'age' (targetting once nodes reach the age \
+                    given by --attackage), 'cohort' (agers and farmers; see --agers), 'merge' \
+                    (withdraw nodes to force merges), 'flood' (flood the join queue; see \
+                    --floodpower), 'all'")
+            (@arg attackage: --attackage [AGE] "Age at which malicious nodes start targetting \
+                    a group with -S age; younger nodes stay where they are put to age. \
+                    Default: 2.")
+            (@arg agers: --agers [PROP] "With -S cohort, the proportion of malicious nodes \
+                    kept to age in the target group; the rest are reset until they join the \
+                    target group, causing churn there. Default: 0.5.")
+            (@arg floodpower: --floodpower [NUM] "With -S flood, the work the attacker spends \
+                    creating extra identities and resetting nodes, in node-days per day. \
+                    Default: 10.")
+            (@arg resetcost: --resetcost [PROP] "With -S flood, the cost of each new identity \
+                    or reset as a proportion of the proof time. Default: 1.")
+            (@arg budget: --budget [NODEDAYS] "Total proof-of-work the attacker can do, in \
+                    node-days; each malicious join attempt or reset costs one proof time (with -S \
+                    flood, --resetcost times that). The attacker stops when this is exhausted. \
+                    Default: unlimited.")
         )
             .get_matches();
 
-        // Create initial parameter set
-        let tool = match matches.value_of("tool").unwrap_or("full") {
-            "calc" => SimType::DirectCalc,
-            "structure" => SimType::Structure,
-            "full" => SimType::FullSim,
-            _ => panic!("unexpected tool"),
+        let batch = parse_opt(&matches, "repetitions", "-p").unwrap_or(100);
+        let repetitions = match parse_opt(&matches, "precision", "--precision") {
+            None => {
+                for &(name, flag) in &[("maxtrials", "--maxtrials"), ("maxtime", "--maxtime")] {
+                    if matches.is_present(name) {
+                        fail(&format!("{} requires --precision", flag));
+                    }
+                }
+                Repetitions::fixed(batch)
+            }
+            Some(p) => {
+                Repetitions {
+                    batch,
+                    precision: Some(p),
+                    max_trials: parse_opt(&matches, "maxtrials", "--maxtrials").unwrap_or(100000),
+                    max_time: parse_opt(&matches, "maxtime", "--maxtime"),
+                }
+            }
         };
 
+        // Create initial parameter set
+        let tool = matches.value_of("tool").unwrap_or("full");
+        let tool = SimType::from_name(tool).unwrap_or_else(|| {
+            fail(&format!("-t {}: expected 'calc', 'structure' or 'full'", tool))
+        });
+
         if matches.is_present("about") {
             println!("About tool {}:", tool.name());
-            println!("");
+            println!();
             match tool {
                 SimType::DirectCalc => {
                     println!("\
@@ -257,16 +276,18 @@ The simulation is divided into two phases: firstly an initial network of good
 (uncompromised) nodes is created, then malicious nodes are added. During the
 first phase, the number of available nodes to be added to the network is fixed
 (--nodes parameter), and all available nodes are good. At the start of the
-second phase, all attacking (malicious) nodes are added to the set of available
-nodes (--attacking parameter), and optionally good nodes are added to the set
-each step (--backjoin parameter).
+second phase, attacking (malicious) nodes are added to the set of available
+nodes (--attacking parameter), all at once or gradually (--arrival parameter),
+and optionally good nodes are added to the set each step (--backjoin
+parameter).
 
 Step length is set based on the proof time. Each step nodes from the set of
 available nodes are added to the network, limited by the maximum join rate.
 When both malicious and good nodes are available, nodes are selected randomly
 according to their ratios. If the leave rate is non-zero, each good node has a
-chance of being removed (malicious nodes are assumed not to leave; all nodes
-which leave are replaced by a new node in the set of available nodes).
+chance of being removed (malicious nodes leave only if the attack strategy
+withdraws them; all nodes which leave are replaced by a new node in the set of
+available nodes). Groups left too small by nodes leaving are merged.
 
 All added nodes are delayed one step to account for proof of work time; when
 actually added, a churn operation happens, which may age and move an existing
@@ -288,353 +309,133 @@ same time to complete proof-of-work.
             process::exit(0);
         }
 
-        let nodes_range: SamplePoints<NN> = matches.value_of("nodes")
-            .map_or(SamplePoints::Number(1000), |s| s.parse().expect("parse"));
-        let mut nodes_iter = nodes_range.iter();
-
-        let at_nodes_range: SamplePoints<RelOrAbs<NN>> = matches.value_of("attacking")
-            .map_or(SamplePoints::Number(RelOrAbs::Rel(0.1)),
-                    |s| s.parse().expect("parse"));
-        let mut at_nodes_iter = at_nodes_range.iter();
-
-        let max_join_range: SamplePoints<RelOrAbs<RR>> = matches.value_of("maxjoin")
-            .map_or(SamplePoints::Number(RelOrAbs::Rel(0.02)),
-                    |s| s.parse().expect("parse"));
-        let mut max_join_iter = max_join_range.iter();
-
-        let add_good_range: SamplePoints<RelOrAbs<RR>> = matches.value_of("backjoin")
-            .map_or(SamplePoints::Number(RelOrAbs::Rel(0.001)),
-                    |s| s.parse().expect("parse"));
-        let mut add_good_iter = add_good_range.iter();
-
-        let leave_good_range: SamplePoints<RelOrAbs<RR>> = matches.value_of("leavegood")
-            .map_or(SamplePoints::Number(RelOrAbs::Rel(0.001)),
-                    |s| s.parse().expect("parse"));
-        let mut leave_good_iter = leave_good_range.iter();
-
-        let group_size_range: SamplePoints<NN> = matches.value_of("group")
-            .map_or(SamplePoints::Number(10), |s| s.parse().expect("parse"));
-        let mut group_size_iter = group_size_range.iter();
-
-        let quorum_range = matches.value_of("quorum")
-            .map_or(SamplePoints::Number(0.5), |s| s.parse().expect("parse"));
-        let mut quorum_iter = quorum_range.iter();
-
-        let proof_time_range: SamplePoints<RR> = matches.value_of("prooftime")
-            .map_or(SamplePoints::Number(1.0), |s| s.parse().expect("parse"));
-        let mut proof_time_iter = proof_time_range.iter();
-
-        let max_days_range: SamplePoints<RR> = matches.value_of("maxdays")
-            .map_or(SamplePoints::Number(100.0), |s| s.parse().expect("parse"));
-        let mut max_days_iter = max_days_range.iter();
-
-        let q_use_age = match matches.value_of("quorum_alg") {
-            None => vec![false],
-            Some("simple") => vec![false],
-            Some("age") => vec![true],
-            Some("all") => vec![false, true],
-            Some(x) => panic!("unexpected: -Q {}", x),
-        };
-        let mut q_use_age_iter = q_use_age.iter();
-
-        let at_type = match matches.value_of("strategy") {
-            None => vec![AttackType::Untargetted],
-            Some("none") => vec![AttackType::Untargetted],
-            Some("simple") => vec![AttackType::SimpleTargetted],
-            Some("all") => vec![AttackType::Untargetted, AttackType::SimpleTargetted],
-            Some(x) => panic!("unexpected: -T {}", x),
-        };
-        let mut at_type_iter = at_type.iter();
-
-        let mut v = vec![SimParams {
-                             sim_type: tool,
-                             num_initial: nodes_iter.next().expect("first iter item"),
-                             num_attacking: at_nodes_iter.next().expect("first iter item"),
-                             max_join: max_join_iter.next().expect("first iter item"),
-                             add_good: add_good_iter.next().expect("first iter item"),
-                             leave_good: leave_good_iter.next().expect("first iter item"),
-                             min_group_size: group_size_iter.next().expect("first iter item"),
-                             quorum_prop: quorum_iter.next().expect("first iter item"),
-                             proof_time: proof_time_iter.next().expect("first iter item"),
-                             max_days: max_days_iter.next().expect("first iter item"),
-                             age_quorum: *q_use_age_iter.next().expect("first iter item"),
-                             targetting: *at_type_iter.next().expect("first iter item"),
-                         }];
-
-        // TODO: check we're not going to cause out-of-memory here!
-
-        // Replicate for all network sizes (num nodes)
-        let range = 0..v.len();
-        for n in nodes_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.num_initial = n;
-                v.push(s);
-            }
+        let mut spec = SweepSpec::new();
+        spec.sim_type = tool;
+        spec.repetitions = repetitions;
+        if let Some(x) = parse_opt(&matches, "nodes", "-n") {
+            spec.nodes = x;
         }
-
-        // Replicate for all numbers of malicious nodes
-        let range = 0..v.len();
-        for r in at_nodes_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.num_attacking = r;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "attacking", "-a") {
+            spec.attacking = x;
         }
-
-        // Replicate for all join rates of good nodes
-        let range = 0..v.len();
-        for x in max_join_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.max_join = x;
-                v.push(s);
-            }
+        if let Some(s) = matches.value_of("arrival") {
+            spec.arrival = s.split(',')
+                .map(|a| Arrival::parse(a).unwrap_or_else(|e| fail(&format!("--arrival: {}", e))))
+                .collect();
         }
-
-        // Replicate for all leave rates of good nodes
-        let range = 0..v.len();
-        for x in add_good_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.add_good = x;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "maxjoin", "-j") {
+            spec.max_join = x;
         }
-
-        // Replicate for all leave rates of good nodes
-        let range = 0..v.len();
-        for x in leave_good_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.leave_good = x;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "backjoin", "-b") {
+            spec.add_good = x;
         }
-
-        // Replicate for all group sizes
-        let range = 0..v.len();
-        for g in group_size_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.min_group_size = g;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "leavegood", "-l") {
+            spec.leave_good = x;
         }
-
-        // Replicate for all quorum sizes
-        let range = 0..v.len();
-        for q in quorum_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.quorum_prop = q;
-                v.push(s);
-            }
+        if let Some(s) = matches.value_of("backjoinsched") {
+            spec.add_schedule = Schedule::parse(s)
+                .unwrap_or_else(|e| fail(&format!("--backjoinsched: {}", e)));
         }
-
-        // Replicate for all proof-of-work times
-        let range = 0..v.len();
-        for x in proof_time_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.proof_time = x;
-                v.push(s);
-            }
+        if let Some(s) = matches.value_of("leavesched") {
+            spec.leave_schedule = Schedule::parse(s)
+                .unwrap_or_else(|e| fail(&format!("--leavesched: {}", e)));
         }
-
-        // Replicate for all max days
-        let range = 0..v.len();
-        for x in max_days_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.max_days = x;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "group", "-g") {
+            spec.group_size = x;
         }
-
-        // Replicate for all quorum types
-        let range = 0..v.len();
-        for q in q_use_age_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.age_quorum = *q;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "quorum", "-q") {
+            spec.quorum = x;
         }
-
-        // Replicate for all attack strategies
-        let range = 0..v.len();
-        for at in at_type_iter {
-            for i in range.clone() {
-                let mut s = v[i].clone();
-                s.targetting = *at;
-                v.push(s);
-            }
+        if let Some(x) = parse_opt(&matches, "agequorum", "--agequorum") {
+            spec.age_quorum_prop = Some(x);
         }
-
-        let repetitions = matches.value_of("repetitions")
-            .map(|s| s.parse().expect("parse"))
-            .unwrap_or(100);
-        (repetitions, v)
-    }
-}
-
-#[derive(Clone, Copy)]
-pub enum SimType {
-    DirectCalc,
-    Structure,
-    FullSim,
-}
-
-impl SimType {
-    pub fn name(self) -> &'static str {
-        match self {
-            SimType::DirectCalc => "dir_calc",
-            SimType::Structure => "structure",
-            SimType::FullSim => "full_sim",
+        if let Some(x) = parse_opt(&matches, "prooftime", "--prooftime") {
+            spec.proof_time = x;
         }
-    }
-}
-
-#[derive(Clone, Copy)]
-
-pub enum AttackType {
-    Untargetted,
-    SimpleTargetted,
-}
-
-impl AttackType {
-    pub fn name(&self) -> &'static str {
-        match self {
-            &AttackType::Untargetted => "untarg.",
-            &AttackType::SimpleTargetted => "simp_targ",
+        if let Some(x) = parse_opt(&matches, "maxdays", "-d") {
+            spec.max_days = x;
         }
-    }
-}
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum RelOrAbs<T> {
-    Rel(RR),
-    Abs(T),
-}
-
-impl RelOrAbs<NN> {
-    pub fn from_base(self, base: RR) -> NN {
-        match self {
-            RelOrAbs::Rel(r) => (base * r).round() as NN,
-            RelOrAbs::Abs(n) => n,
+        if let Some(s) = matches.value_of("quorum_alg") {
+            spec.quorum_alg = QuorumAlg::from_name(s)
+                .unwrap_or_else(|| {
+                    fail(&format!("-Q {}: expected 'simple', 'age', 'elder', 'age_linear', \
+                                   'age_exp', 'age_capped', 'age_sqrt', 'weighted', 'bft' or \
+                                   'all'",
+                                  s))
+                });
         }
-    }
-}
-
-impl RelOrAbs<RR> {
-    pub fn from_base(self, base: RR) -> RR {
-        match self {
-            RelOrAbs::Rel(r) => base * r,
-            RelOrAbs::Abs(n) => n,
+        if let Some(x) = parse_opt(&matches, "elders", "--elders") {
+            spec.elders = x;
         }
-    }
-}
-
-impl<T: FromStr> FromStr for RelOrAbs<T>
-    where <T as FromStr>::Err: Debug
-{
-    type Err = ();  // we just panic!
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.ends_with('%') {
-            let mut s = s.to_string();
-            let _ = s.pop();
-            let perc = s.parse::<RR>().expect("parse");
-            Ok(RelOrAbs::Rel(perc * 0.01))
-        } else {
-            Ok(RelOrAbs::Abs(s.parse().expect("parse")))
+        if let Some(x) = parse_opt(&matches, "agecap", "--agecap") {
+            spec.age_cap = x;
         }
-    }
-}
-
-impl<T: AddAssign + Copy> AddAssign for RelOrAbs<T> {
-    fn add_assign(&mut self, rhs: RelOrAbs<T>) {
-        match (self, rhs) {
-            (&mut RelOrAbs::Rel(ref mut x), RelOrAbs::Rel(y)) => *x += y,
-            (&mut RelOrAbs::Abs(ref mut x), RelOrAbs::Abs(y)) => *x += y,
-            _ => panic!("wrong rel/abs type!"),
+        if let Some(s) = matches.value_of("strategy") {
+            spec.targetting = AttackType::from_name(s)
+                .unwrap_or_else(|| {
+                    fail(&format!("-S {}: expected 'none', 'simple', 'age', 'cohort', 'merge', \
+                                   'flood' or 'all'",
+                                  s))
+                });
         }
-    }
-}
-
-impl<T: PartialOrd<T> + Copy> PartialOrd<RelOrAbs<T>> for RelOrAbs<T> {
-    fn partial_cmp(&self, rhs: &RelOrAbs<T>) -> Option<Ordering> {
-        match (self, rhs) {
-            (&RelOrAbs::Rel(x), &RelOrAbs::Rel(ref y)) => x.partial_cmp(y),
-            (&RelOrAbs::Abs(x), &RelOrAbs::Abs(ref y)) => x.partial_cmp(y),
-            _ => panic!("wrong rel/abs type!"),
+        if let Some(x) = parse_opt(&matches, "attackage", "--attackage") {
+            spec.attack_age = x;
         }
-    }
-}
-
-impl<T: From<u32>> DefaultStep<RelOrAbs<T>> for RelOrAbs<T> {
-    fn default_step(x: RelOrAbs<T>) -> RelOrAbs<T> {
-        match x {
-            RelOrAbs::Rel(_) => RelOrAbs::Rel(0.1),
-            RelOrAbs::Abs(_) => RelOrAbs::Abs(1.into()),
+        if let Some(x) = parse_opt(&matches, "agers", "--agers") {
+            spec.ager_prop = x;
+        }
+        if let Some(x) = parse_opt(&matches, "floodpower", "--floodpower") {
+            spec.flood_power = x;
+        }
+        if let Some(x) = parse_opt(&matches, "resetcost", "--resetcost") {
+            spec.reset_cost = x;
+        }
+        if let Some(x) = parse_opt(&matches, "budget", "--budget") {
+            spec.budget = Some(x);
         }
-    }
-}
-
-#[derive(Clone)]
-pub struct SimParams {
-    pub sim_type: SimType,
-    pub age_quorum: bool,
-    pub targetting: AttackType,
-    pub num_initial: NN,
-    pub num_attacking: RelOrAbs<NN>,
-    pub max_join: RelOrAbs<RR>,
-    pub add_good: RelOrAbs<RR>,
-    pub leave_good: RelOrAbs<RR>,
-    pub min_group_size: NN,
-    pub quorum_prop: RR,
-    pub proof_time: RR,
-    pub max_days: RR,
-}
-
-impl SimParams {
-    pub fn result(&self, repetitions: u32) -> (ToolArgs, SimResult) {
-        let args = ToolArgs::from_params(self);
 
-        let result = {
-            let tool: Box<Tool> = match self.sim_type {
-                SimType::DirectCalc => Box::new(DirectCalcTool::new(&args)),
-                SimType::Structure => Box::new(SimStructureTool::new(&args)),
-                SimType::FullSim => {
-                    // note: FullSimTool is templated on quorum and attack strategy parameters, so
-                    // we need to create the whole thing at once (not create parameters first)
-                    match (self.age_quorum, self.targetting) {
-                        (false, AttackType::Untargetted) => {
-                            Box::new(FullSimTool::new(&args,
-                                                      SimpleQuorum::new(),
-                                                      UntargettedAttack {}))
-                        }
-                        (true, AttackType::Untargetted) => {
-                            Box::new(FullSimTool::new(&args,
-                                                      AgeQuorum::new(),
-                                                      UntargettedAttack {}))
-                        }
-                        (false, AttackType::SimpleTargetted) => {
-                            Box::new(FullSimTool::new(&args,
-                                                      SimpleQuorum::new(),
-                                                      SimpleTargettedAttack::new()))
-                        }
-                        (true, AttackType::SimpleTargetted) => {
-                            Box::new(FullSimTool::new(&args,
-                                                      AgeQuorum::new(),
-                                                      SimpleTargettedAttack::new()))
-                        }
-                    }
+        let mut file_seed = None;
+        let v = match matches.value_of("experiment") {
+            Some(path) => {
+                // Parameters come only from the file
+                if let Some(&(_, flag)) = SWEEP_ARGS.iter().find(|a| matches.is_present(a.0)) {
+                    fail(&format!("-e cannot be combined with {}; set parameters in the \
+                                   experiment file",
+                                  flag));
                 }
-            };
-            tool.calc_p_compromise(repetitions)
+                let experiment = load_experiment(path).unwrap_or_else(|e| fail(&e));
+                file_seed = experiment.seed;
+                experiment.params
+            }
+            // TODO: check we're not going to cause out-of-memory here!
+            None => {
+                spec.check().unwrap_or_else(|e| fail(&e.to_string()));
+                spec.expand()
+            }
+        };
+
+        let seed = parse_opt(&matches, "seed", "--seed").or(file_seed);
+        let format = match matches.value_of("format").unwrap_or("table") {
+            "table" => Format::Table,
+            "csv" => Format::Csv,
+            "json" => Format::Json,
+            x => fail(&format!("-f {}: expected 'table', 'csv' or 'json'", x)),
+        };
+        let options = RunOptions {
+            seed,
+            format,
+            output: matches.value_of("output").map(|s| s.to_string()),
+            trace: matches.value_of("trace").map(|s| s.to_string()),
+            resume: matches.is_present("resume"),
         };
-        (args, result)
+        if options.resume {
+            if options.output.is_none() {
+                fail("--resume requires --output");
+            }
+            if options.format == Format::Table {
+                fail("--resume requires --format csv or --format json");
+            }
+        }
+        (options, v)
     }
 }
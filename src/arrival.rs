@@ -0,0 +1,136 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Arrival profiles of malicious nodes
+//!
+//! A profile says how many of the attacking nodes have become available to join by each day of
+//! the attack. Every profile eventually delivers the same total, so profiles can be compared for
+//! the same attacker size. Profiles are written:
+//!
+//! *   `once` — all nodes at the start of the attack (the default)
+//! *   `rate:R` — `R` nodes per day until all have arrived
+//! *   `ramp:D` — an arrival rate increasing linearly from zero, such that all have arrived after
+//!     `D` days
+//! *   `file:PATH` — a step schedule: each line of the file gives a day and a weight (e.g.
+//!     `30, 0.5`), and a share of nodes proportional to the weight arrives on that day. Blank
+//!     lines and lines starting with `#` are ignored.
+
+use std::fmt::{self, Display, Formatter};
+
+use {NN, RR};
+use schedule::read_points;
+
+
+/// How malicious nodes arrive during the attack
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arrival {
+    /// All at the start of the attack
+    Once,
+    /// Constant number of nodes per day
+    Rate(RR),
+    /// Linearly increasing rate, all arrived after the given number of days
+    Ramp(RR),
+    /// Schedule read from a file: path, and `(day, proportion arrived by then)` in order of day
+    Schedule(String, Vec<(RR, RR)>),
+}
+
+impl Arrival {
+    /// Parse a profile as written on the command line (reading the file of a schedule).
+    pub fn parse(s: &str) -> Result<Arrival, String> {
+        let positive = |x: &str| {
+            x.parse::<RR>()
+                .ok()
+                .filter(|&x| x > 0.0)
+                .ok_or_else(|| format!("arrival profile '{}': expected a positive number", s))
+        };
+        if s == "once" {
+            Ok(Arrival::Once)
+        } else if let Some(rate) = s.strip_prefix("rate:") {
+            Ok(Arrival::Rate(positive(rate)?))
+        } else if let Some(days) = s.strip_prefix("ramp:") {
+            Ok(Arrival::Ramp(positive(days)?))
+        } else if let Some(path) = s.strip_prefix("file:") {
+            let schedule = cumulative(read_points(path)?)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            Ok(Arrival::Schedule(path.to_string(), schedule))
+        } else {
+            Err(format!("arrival profile '{}': expected 'once', 'rate:R', 'ramp:D' or \
+                         'file:PATH'",
+                        s))
+        }
+    }
+
+    /// Number of the `total` malicious nodes which have arrived by `day` days into the attack.
+    pub fn arrived(&self, total: NN, day: RR) -> NN {
+        let prop = match *self {
+            Arrival::Once => 1.0,
+            Arrival::Rate(rate) => rate * day / total as RR,
+            Arrival::Ramp(days) => (day / days).powi(2),
+            Arrival::Schedule(_, ref schedule) => {
+                schedule.iter().take_while(|s| s.0 <= day).last().map_or(0.0, |s| s.1)
+            }
+        };
+        (prop.min(1.0) * total as RR).floor() as NN
+    }
+}
+
+impl Display for Arrival {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Arrival::Once => write!(f, "once"),
+            Arrival::Rate(rate) => write!(f, "rate:{}", rate),
+            Arrival::Ramp(days) => write!(f, "ramp:{}", days),
+            Arrival::Schedule(ref path, _) => write!(f, "file:{}", path),
+        }
+    }
+}
+
+// Convert weights of points (sorted by day) to cumulative proportions.
+fn cumulative(points: Vec<(RR, RR)>) -> Result<Vec<(RR, RR)>, String> {
+    let total: RR = points.iter().map(|p| p.1).sum();
+    if total <= 0.0 {
+        return Err("expected at least one positive weight".to_string());
+    }
+    let mut sum = 0.0;
+    Ok(points.into_iter()
+        .map(|(day, weight)| {
+            sum += weight;
+            (day, sum / total)
+        })
+        .collect())
+}
+
+#[test]
+fn test_arrival() {
+    assert_eq!(Arrival::parse("once").expect("once").arrived(100, 0.0), 100);
+    let rate = Arrival::parse("rate:4").expect("rate");
+    assert_eq!(rate.arrived(100, 0.0), 0);
+    assert_eq!(rate.arrived(100, 10.0), 40);
+    assert_eq!(rate.arrived(100, 50.0), 100);
+    let ramp = Arrival::parse("ramp:10").expect("ramp");
+    assert_eq!(ramp.arrived(100, 5.0), 25);
+    assert_eq!(ramp.arrived(100, 20.0), 100);
+    assert!(Arrival::parse("ramp:0").is_err());
+    assert!(Arrival::parse("slow").is_err());
+
+    let schedule = cumulative(vec![(0.0, 1.0), (20.0, 3.0)]).expect("schedule");
+    assert_eq!(schedule, vec![(0.0, 0.25), (20.0, 1.0)]);
+    let arrival = Arrival::Schedule("x".to_string(), schedule);
+    assert_eq!(arrival.arrived(100, 19.0), 25);
+    assert_eq!(arrival.arrived(100, 20.0), 100);
+    assert!(cumulative(vec![(0.0, 0.0)]).is_err());
+}
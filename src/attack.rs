@@ -17,8 +17,11 @@
 
 // Attack strategies
 
-use node::{Prefix, NodeName, NodeData};
-use net::Network;
+use std::collections::HashMap;
+
+use {NN, RR};
+use node::{Prefix, NodeName, NodeData, Cohort};
+use net::{Network, Group, DetHasher};
 
 
 /// Determines a few things about how attacks work.
@@ -37,9 +40,74 @@ pub trait AttackStrategy {
                 _node_data: &NodeData) {
     }
 
+    /// Called when groups merge on all malicious nodes in the merged group, with the prefix of
+    /// their old group and of the merged group.
+    ///
+    /// Default implementation: do nothing.
+    fn on_merge(&mut self,
+                _old_prefix: Prefix,
+                _new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+    }
+
+    /// Called after a churn event in the group with prefix `prefix`, caused by `new_node` joining.
+    /// Nodes in the group have been aged; any node relocated has been removed (and
+    /// `on_relocate` called if it is malicious).
+    ///
+    /// Default implementation: do nothing.
+    fn on_churn(&mut self, _net: &Network, _prefix: Prefix, _new_node: NodeName) {}
+
+    /// Called when a malicious node is removed from its group (with prefix `old_prefix`) for
+    /// relocation. `reset_on_new_name` is called afterwards with its new name.
+    ///
+    /// Default implementation: do nothing.
+    fn on_relocate(&mut self, _old_prefix: Prefix, _old_name: NodeName, _node_data: &NodeData) {}
+
+    /// Called when a malicious node which completed proof-of-work is not accepted by its group
+    /// (due to an `AddRestriction` or a name collision). `reset_on_new_name` is called afterwards
+    /// with its new name.
+    ///
+    /// Default implementation: do nothing.
+    fn on_rejected(&mut self, _net: &Network, _node_name: NodeName, _node_data: &NodeData) {}
+
+    /// Called each step to let the attacker withdraw malicious nodes from the network. Returns
+    /// the names of malicious nodes (in groups) which should leave now. Groups left too small
+    /// are merged (`on_merge` is called as usual), and each node leaving is replaced by a new
+    /// node in the queue of available malicious nodes, thus may rejoin under a new name.
+    ///
+    /// Default implementation: no nodes leave.
+    fn choose_leaving(&mut self, _net: &Network) -> Vec<NodeName> {
+        vec![]
+    }
+
+    /// Called each step, after nodes which completed proof-of-work have been added and before new
+    /// nodes are taken from the queue of available nodes. Returns the number of extra malicious
+    /// identities the attacker adds to the queue now.
+    ///
+    /// Default implementation: none.
+    fn new_identities(&mut self, _net: &Network) -> NN {
+        0
+    }
+
+    /// Cost of each malicious join attempt (a node taken from the queue, including after a
+    /// reset) as a proportion of the proof time; this is what is charged to the attacker's
+    /// budget.
+    ///
+    /// Default implementation: one proof time.
+    fn identity_cost(&self) -> RR {
+        1.0
+    }
+
+    /// Called at the end of each step of the simulation.
+    ///
+    /// Default implementation: do nothing.
+    fn on_step(&mut self, _net: &Network) {}
+
     /// Called when a malicious node is added or moved and told its new name. If moved, the method
     /// is also passed the old name. This should return true only
     /// if the attacker decides to reset this malicious node now (before doing proof-of-work).
+    /// The node's data may be modified to set its cohort (see `Cohort`).
     ///
     /// Group prefix can be obtained via `net.find_prefix(name)`.
     ///
@@ -48,7 +116,7 @@ pub trait AttackStrategy {
                          _net: &Network,
                          _old_name: Option<NodeName>,
                          _new_name: NodeName,
-                         _node_data: &NodeData)
+                         _node_data: &mut NodeData)
                          -> bool {
         false
     }
@@ -73,6 +141,12 @@ impl SimpleTargettedAttack {
     }
 }
 
+impl Default for SimpleTargettedAttack {
+    fn default() -> Self {
+        SimpleTargettedAttack::new()
+    }
+}
+
 impl AttackStrategy for SimpleTargettedAttack {
     fn on_split(&mut self,
                 old_prefix: Prefix,
@@ -84,11 +158,21 @@ impl AttackStrategy for SimpleTargettedAttack {
         }
     }
 
+    fn on_merge(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
     fn reset_on_new_name(&mut self,
                          net: &Network,
                          _old_name: Option<NodeName>,
                          new_name: NodeName,
-                         _node_data: &NodeData)
+                         _node_data: &mut NodeData)
                          -> bool {
         let prefix = net.find_prefix(new_name);
         if let Some(target) = self.target {
@@ -101,3 +185,430 @@ impl AttackStrategy for SimpleTargettedAttack {
         }
     }
 }
+
+/// Strategy which targets a group, but lets nodes age first (so that it can be used against
+/// age-based quorums).
+///
+/// Nodes younger than the age threshold stay wherever they land. A node moved with at least this
+/// age is reset if it lands outside the target group (which is the group of the first such node).
+/// Resetting a node loses its age: its replacement starts at age 0, so stays wherever it lands
+/// until it matures in turn. With threshold 0 this is the same as `SimpleTargettedAttack`.
+#[derive(Clone)]
+pub struct AgeTargettedAttack {
+    target: Option<Prefix>,
+    min_age: u32,
+}
+
+impl AgeTargettedAttack {
+    /// Create, with the age at which nodes start targetting
+    pub fn new(min_age: u32) -> Self {
+        AgeTargettedAttack {
+            target: None,
+            min_age,
+        }
+    }
+}
+
+impl AttackStrategy for AgeTargettedAttack {
+    fn on_split(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn on_merge(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn reset_on_new_name(&mut self,
+                         net: &Network,
+                         _old_name: Option<NodeName>,
+                         new_name: NodeName,
+                         node_data: &mut NodeData)
+                         -> bool {
+        if node_data.age() < self.min_age {
+            // still maturing: stay wherever we are put
+            return false;
+        }
+        let prefix = net.find_prefix(new_name);
+        if let Some(target) = self.target {
+            prefix != target
+        } else {
+            self.target = Some(prefix);
+            false
+        }
+    }
+}
+
+/// Strategy with two cohorts of malicious nodes, both joining a target group: "agers", which
+/// are kept to age and never reset once they have joined, and "farmers", which are reset every
+/// time they would land outside the target group, so that all their joins cause churn events
+/// (thus ageing) in the target group.
+///
+/// The proportion of malicious nodes which are agers is configurable. Nodes which are reset are
+/// replaced by nodes of the same cohort.
+#[derive(Clone)]
+pub struct TwoCohortAttack {
+    target: Option<Prefix>,
+    ager_prop: RR,
+    // numbers of nodes assigned to cohorts (not counting replacements)
+    num_agers: NN,
+    num_assigned: NN,
+    // cohorts of nodes reset, awaiting replacement
+    replacements: Vec<Cohort>,
+}
+
+impl TwoCohortAttack {
+    /// Cohort of nodes kept to age
+    pub const AGER: Cohort = 1;
+    /// Cohort of nodes cycled through the target group
+    pub const FARMER: Cohort = 2;
+
+    /// Create, with the proportion of malicious nodes which are agers
+    pub fn new(ager_prop: RR) -> Self {
+        TwoCohortAttack {
+            target: None,
+            ager_prop,
+            num_agers: 0,
+            num_assigned: 0,
+            replacements: vec![],
+        }
+    }
+
+    // Choose a cohort for a new node
+    fn assign(&mut self) -> Cohort {
+        if let Some(cohort) = self.replacements.pop() {
+            return cohort;
+        }
+        self.num_assigned += 1;
+        if (self.num_agers as RR) < self.ager_prop * (self.num_assigned as RR) {
+            self.num_agers += 1;
+            Self::AGER
+        } else {
+            Self::FARMER
+        }
+    }
+}
+
+impl AttackStrategy for TwoCohortAttack {
+    fn on_split(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                node_data: &NodeData) {
+        // follow the agers
+        if self.target == Some(old_prefix) && node_data.cohort() == Self::AGER {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn on_merge(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn reset_on_new_name(&mut self,
+                         net: &Network,
+                         old_name: Option<NodeName>,
+                         new_name: NodeName,
+                         node_data: &mut NodeData)
+                         -> bool {
+        if node_data.cohort() == 0 {
+            let cohort = self.assign();
+            node_data.set_cohort(cohort);
+        }
+        if node_data.cohort() == Self::AGER && old_name.is_some() {
+            // agers keep their age wherever they are moved
+            return false;
+        }
+        let prefix = net.find_prefix(new_name);
+        let target = match self.target {
+            // the target may have split without agers
+            Some(target) if net.groups().contains_key(&target) => target,
+            _ => {
+                self.target = Some(prefix);
+                prefix
+            }
+        };
+        if prefix != target {
+            self.replacements.push(node_data.cohort());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Strategy which forces merges: when the attacker has enough nodes in a group that withdrawing
+/// some would leave it too small, it may withdraw them so that the group is merged into its
+/// parent. This is done when the proportion of malicious nodes in the merged group would be
+/// higher than it is now in the other groups being merged (i.e. the attacker concentrates its
+/// remaining nodes from the group into its siblings).
+///
+/// Nodes join without targetting; withdrawn nodes (the youngest in their group) rejoin under new
+/// names. At most one merge is forced per step: the one giving the highest proportion.
+#[derive(Clone)]
+pub struct MergeForcingAttack;
+
+impl AttackStrategy for MergeForcingAttack {
+    fn choose_leaving(&mut self, net: &Network) -> Vec<NodeName> {
+        let min_size = net.min_group_size();
+        let groups = net.groups();
+        let count_malicious = |group: &Group| group.values().filter(|d| d.is_malicious()).count();
+
+        // Total size and number of malicious nodes of all groups which would be merged into
+        // each parent of a group.
+        let mut merged: HashMap<Prefix, (usize, usize), DetHasher> = groups.keys()
+            .filter(|p| p.bit_count() > 0)
+            .map(|p| (p.popped(), (0, 0)))
+            .collect();
+        for (prefix, group) in groups {
+            let n_mal = count_malicious(group);
+            let mut ancestor = *prefix;
+            while ancestor.bit_count() > 0 {
+                ancestor = ancestor.popped();
+                if let Some(m) = merged.get_mut(&ancestor) {
+                    m.0 += group.len();
+                    m.1 += n_mal;
+                }
+            }
+        }
+
+        // Find the group whose forced merge gives the highest proportion
+        let mut best: Option<(Prefix, usize, RR)> = None;
+        for (prefix, group) in groups {
+            if prefix.bit_count() == 0 || group.len() < min_size {
+                continue;
+            }
+            let n_leave = group.len() + 1 - min_size;
+            let n_mal = count_malicious(group);
+            if n_mal < n_leave {
+                continue;
+            }
+            let (len, mal) = merged[&prefix.popped()];
+            let others = (mal - n_mal) as RR / (len - group.len()) as RR;
+            let prop = (mal - n_leave) as RR / (len - n_leave) as RR;
+            if prop > others && best.is_none_or(|b| prop > b.2) {
+                best = Some((*prefix, n_leave, prop));
+            }
+        }
+
+        let (prefix, n_leave, _) = match best {
+            Some(b) => b,
+            None => return vec![],
+        };
+        let mut malicious: Vec<(u32, NodeName)> = groups[&prefix]
+            .iter()
+            .filter(|node| node.1.is_malicious())
+            .map(|(name, data)| (data.age(), *name))
+            .collect();
+        malicious.sort();
+        malicious.into_iter().take(n_leave).map(|node| node.1).collect()
+    }
+}
+
+/// Strategy which floods the join queue with disposable identities, so that malicious nodes take
+/// a larger share of the limited number of joins, and resets them until they land in a target
+/// group.
+///
+/// Identities and resets have a cost: once its first node joins, the attacker gains credit for a
+/// fixed number of identities each step (its compute power divided by the cost of each). Resets
+/// are paid for first: credit is used for them during the step, and whole credits left at the
+/// end of the step are spent on new identities at the start of the next. Nodes which cannot be
+/// reset for lack of credit stay where they land.
+///
+/// Credit only limits the rate of work. With a budget, the same per-identity cost is charged
+/// (once) against the budget when each identity is taken from the queue.
+#[derive(Clone)]
+pub struct FloodAttack {
+    target: Option<Prefix>,
+    per_step: RR,
+    cost: RR,
+    credit: RR,
+    // identities paid for by credit left over from the last step
+    spare: NN,
+}
+
+impl FloodAttack {
+    /// Create, with the number of identities (or resets) the attacker can afford per step and
+    /// the cost of each as a proportion of the proof time
+    pub fn new(per_step: RR, cost: RR) -> Self {
+        FloodAttack {
+            target: None,
+            per_step,
+            cost,
+            credit: 0.0,
+            spare: 0,
+        }
+    }
+}
+
+impl AttackStrategy for FloodAttack {
+    fn on_split(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn on_merge(&mut self,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                _node_name: NodeName,
+                _node_data: &NodeData) {
+        if self.target == Some(old_prefix) {
+            self.target = Some(new_prefix);
+        }
+    }
+
+    fn new_identities(&mut self, _net: &Network) -> NN {
+        let n = self.spare;
+        self.spare = 0;
+        n
+    }
+
+    fn identity_cost(&self) -> RR {
+        self.cost
+    }
+
+    fn on_step(&mut self, _net: &Network) {
+        // The target is chosen when the first malicious node joins; nothing is spent before.
+        if self.target.is_some() {
+            let n = self.credit.floor();
+            self.spare += n as NN;
+            self.credit += self.per_step - n;
+        }
+    }
+
+    fn reset_on_new_name(&mut self,
+                         net: &Network,
+                         _old_name: Option<NodeName>,
+                         new_name: NodeName,
+                         _node_data: &mut NodeData)
+                         -> bool {
+        let prefix = net.find_prefix(new_name);
+        let target = *self.target.get_or_insert(prefix);
+        if prefix != target && self.credit >= 1.0 {
+            self.credit -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Network of nodes named `i << 61` (so that the first bit selects the half of the name space),
+// with given malicious flags and ages, split into groups as nodes are added.
+#[cfg(test)]
+fn make_network(min_group_size: usize, nodes: &[(NodeName, bool, u32)]) -> Network {
+    use net::NoAddRestriction;
+    use prob::make_rng;
+
+    let mut net = Network::new(min_group_size, make_rng(0));
+    for &(i, malicious, age) in nodes {
+        let name = i << 61;
+        let mut data = NodeData::new(malicious);
+        for _ in 0..age {
+            data.incr_age();
+        }
+        let prefix = net.add_node::<NoAddRestriction>(name, data).expect("add");
+        net.maybe_split(prefix, name, &mut UntargettedAttack);
+    }
+    net
+}
+
+#[test]
+fn test_flood_resets() {
+    let nodes: Vec<_> = [0, 1, 2, 4, 5, 6].iter().map(|&i| (i, false, 1)).collect();
+    let net = make_network(2, &nodes);
+    assert_eq!(net.groups().len(), 2);
+    let (in_target, elsewhere) = (5 << 61, 1 << 61);
+    let mut attack = FloodAttack::new(2.0, 1.0);
+    // The first join chooses the target; there is no credit yet
+    assert!(!attack.reset_on_new_name(&net, None, in_target, &mut NodeData::new(true)));
+    attack.on_step(&net);
+    // Credit is kept for resets in the next step, rather than spent on identities first
+    assert_eq!(attack.new_identities(&net), 0);
+    assert!(attack.reset_on_new_name(&net, None, elsewhere, &mut NodeData::new(true)));
+    assert!(!attack.reset_on_new_name(&net, None, in_target, &mut NodeData::new(true)));
+    attack.on_step(&net);
+    // The credit left over pays for a new identity
+    assert_eq!(attack.new_identities(&net), 1);
+}
+
+#[test]
+fn test_age_targetted_resets() {
+    let nodes: Vec<_> = [0, 1, 2, 4, 5, 6].iter().map(|&i| (i, false, 1)).collect();
+    let net = make_network(2, &nodes);
+    let (in_target, elsewhere) = (5 << 61, 1 << 61);
+    let mut attack = AgeTargettedAttack::new(2);
+    let mut young = NodeData::new(true);
+    young.incr_age();
+    let mut mature = young;
+    mature.incr_age();
+    // Young nodes never choose the target nor get reset
+    assert!(!attack.reset_on_new_name(&net, Some(0), elsewhere, &mut young));
+    assert!(!attack.reset_on_new_name(&net, Some(0), in_target, &mut mature));
+    // Only matured nodes outside the target are reset
+    assert!(!attack.reset_on_new_name(&net, Some(0), elsewhere, &mut young));
+    assert!(attack.reset_on_new_name(&net, Some(0), elsewhere, &mut mature));
+    assert!(!attack.reset_on_new_name(&net, Some(0), in_target, &mut mature));
+}
+
+#[test]
+fn test_two_cohort_resets() {
+    let nodes: Vec<_> = [0, 1, 2, 4, 5, 6].iter().map(|&i| (i, false, 1)).collect();
+    let net = make_network(2, &nodes);
+    let (in_target, elsewhere) = (5 << 61, 1 << 61);
+    let mut attack = TwoCohortAttack::new(0.5);
+    // The first node is an ager and chooses the target; once joined, agers stay wherever moved
+    let mut ager = NodeData::new(true);
+    assert!(!attack.reset_on_new_name(&net, None, in_target, &mut ager));
+    assert_eq!(ager.cohort(), TwoCohortAttack::AGER);
+    assert!(!attack.reset_on_new_name(&net, Some(in_target), elsewhere, &mut ager));
+    // Farmers are reset whenever outside the target, and replaced by farmers
+    let mut farmer = NodeData::new(true);
+    assert!(attack.reset_on_new_name(&net, None, elsewhere, &mut farmer));
+    assert_eq!(farmer.cohort(), TwoCohortAttack::FARMER);
+    let mut farmer = NodeData::new(true);
+    assert!(!attack.reset_on_new_name(&net, None, in_target, &mut farmer));
+    assert_eq!(farmer.cohort(), TwoCohortAttack::FARMER);
+    assert!(attack.reset_on_new_name(&net, Some(in_target), elsewhere, &mut farmer));
+}
+
+#[test]
+fn test_merge_forcing_leaves() {
+    // One malicious node of three in the first group, all three in the second
+    let nodes = [(0, false, 0), (1, true, 0), (2, false, 0),
+                 (4, true, 3), (5, true, 0), (6, true, 0)];
+    let mut net = make_network(2, &nodes);
+    assert_eq!(net.groups().len(), 2);
+    let mut attack = MergeForcingAttack;
+    // The youngest nodes of the second group leave, so that the merged group has a higher
+    // proportion than the first
+    let leaving = attack.choose_leaving(&net);
+    assert_eq!(leaving, vec![5 << 61, 6 << 61]);
+    assert_eq!(net.withdraw_malicious(&leaving, &mut attack), 2);
+    let stats = net.stats();
+    assert_eq!((stats.groups, stats.good, stats.malicious), (1, 2, 2));
+    // Nothing more to force
+    assert!(attack.choose_leaving(&net).is_empty());
+}
@@ -0,0 +1,350 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Experiment files
+//!
+//! An experiment file (TOML, or JSON if the name ends with `.json`) lists named scenarios, each
+//! of which describes a parameter sweep. For example:
+//!
+//! ```toml
+//! seed = 42
+//!
+//! [[scenario]]
+//! name = "group-size"
+//! quorum_alg = ["simple", "age"]
+//! attacking = "10%-30%:10%"
+//! min_group = [8, 10, 12]
+//! repetitions = 200
+//!
+//! [[scenario]]
+//! name = "selected"
+//! strategy = "simple"
+//! points = [ { nodes = 1000, attacking = "10%" },
+//!            { nodes = 5000, attacking = "5%", min_group = 12 } ]
+//! ```
+//!
+//! Keys have the same names as the output columns. Parameter values may be numbers, strings
+//! using the command-line syntax for ranges (e.g. `"10%-20%:5%"`) or lists of single values.
+//! Unless `points` is given, every combination of values is simulated. If `points` is given,
+//! each point fixes the parameters it lists, and every combination of the remaining
+//! parameters is simulated for each point.
+//!
+//! Values are checked as for the command line (`SweepSpec::check`), and command-line options
+//! setting parameters cannot be combined with an experiment file.
+
+use std::fs::File;
+use std::io::Read;
+use std::ops::AddAssign;
+use std::str::FromStr;
+
+use rustc_serialize::json::Json;
+use toml;
+
+use NN;
+use arrival::Arrival;
+use schedule::Schedule;
+use params::{SimParams, SimType, AttackType, SamplePoints, SweepSpec, ParseError, QuorumAlg};
+
+
+/// Contents of an experiment file
+pub struct Experiment {
+    /// Master seed, if given in the file
+    pub seed: Option<NN>,
+    /// All parameter sets of all scenarios, in order
+    pub params: Vec<SimParams>,
+}
+
+/// Read an experiment from a file.
+pub fn load_experiment(path: &str) -> Result<Experiment, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("unable to read {}: {}", path, e))?;
+    let root = if path.ends_with(".json") {
+        Json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+    } else {
+        let mut parser = toml::Parser::new(&text);
+        match parser.parse() {
+            Some(table) => toml_to_json(toml::Value::Table(table)),
+            None => {
+                let msgs: Vec<String> = parser.errors
+                    .iter()
+                    .map(|e| {
+                        let (line, col) = parser.to_linecol(e.lo);
+                        format!("{}:{}:{}: {}", path, line + 1, col + 1, e.desc)
+                    })
+                    .collect();
+                return Err(msgs.join("\n"));
+            }
+        }
+    };
+    parse_experiment(&root).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Convert TOML to JSON so that both file types can be handled the same way.
+fn toml_to_json(value: toml::Value) -> Json {
+    match value {
+        toml::Value::String(s) |
+        toml::Value::Datetime(s) => Json::String(s),
+        toml::Value::Integer(i) => Json::I64(i),
+        toml::Value::Float(x) => Json::F64(x),
+        toml::Value::Boolean(b) => Json::Boolean(b),
+        toml::Value::Array(v) => Json::Array(v.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            Json::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+fn parse_experiment(root: &Json) -> Result<Experiment, String> {
+    let root = root.as_object().ok_or("expected a table at top level")?;
+    let mut seed = None;
+    let mut params = vec![];
+    for (key, value) in root {
+        match key.as_str() {
+            "seed" => {
+                seed = Some(value.as_u64().ok_or("seed: expected a non-negative integer")?);
+            }
+            "scenario" => {
+                let scenarios = value.as_array().ok_or("scenario: expected a list of tables")?;
+                for scenario in scenarios {
+                    params.extend(parse_scenario(scenario)?);
+                }
+            }
+            _ => return Err(format!("unknown key '{}'", key)),
+        }
+    }
+    if params.is_empty() {
+        return Err("no scenarios".to_string());
+    }
+    Ok(Experiment { seed, params })
+}
+
+fn parse_scenario(scenario: &Json) -> Result<Vec<SimParams>, String> {
+    let table = scenario.as_object().ok_or("scenario: expected a table")?;
+    let name = table.get("name")
+        .and_then(|n| n.as_string())
+        .ok_or("scenario: expected a 'name'")?;
+    let err = |e: String| format!("scenario {}: {}", name, e);
+
+    let mut spec = SweepSpec::new();
+    spec.scenario = Some(name.to_string());
+    // As on the command line; only used with a precision
+    spec.repetitions.max_trials = 100000;
+    let mut points = None;
+    for (key, value) in table {
+        match key.as_str() {
+            "name" => {}
+            "points" => {
+                points = Some(value.as_array().ok_or_else(|| err("points: expected a list".into()))?);
+            }
+            _ => set_key(&mut spec, key, value).map_err(&err)?,
+        }
+    }
+
+    let points = match points {
+        Some(points) => points,
+        None => {
+            spec.check().map_err(|e| err(e.to_string()))?;
+            return Ok(spec.expand());
+        }
+    };
+    let mut params = vec![];
+    for point in points {
+        let point = point.as_object().ok_or_else(|| err("points: expected tables".into()))?;
+        let mut point_spec = spec.clone();
+        for (key, value) in point {
+            set_key(&mut point_spec, key, value).map_err(&err)?;
+        }
+        point_spec.check().map_err(|e| err(e.to_string()))?;
+        params.extend(point_spec.expand());
+    }
+    Ok(params)
+}
+
+// Set one parameter of `spec` from a key-value pair.
+fn set_key(spec: &mut SweepSpec, key: &str, value: &Json) -> Result<(), String> {
+    let err = |what: &str| format!("{}: expected {}, found {}", key, what, value);
+    match key {
+        "tool" => {
+            spec.sim_type = value.as_string()
+                .and_then(SimType::from_name)
+                .ok_or_else(|| err("'calc', 'structure' or 'full'"))?;
+        }
+        "quorum_alg" => {
+            spec.quorum_alg = names(value, QuorumAlg::from_name)
+                .ok_or_else(|| err("'simple', 'age', 'elder', 'age_linear', 'age_exp', 'age_capped', \
+                                  'age_sqrt', 'weighted', 'bft' or 'all' (or a list)"))?;
+        }
+        "strategy" => {
+            spec.targetting = names(value, AttackType::from_name)
+                .ok_or_else(|| err("'none', 'simple', 'age', 'cohort', 'merge', 'flood' or 'all' \
+                              (or a list)"))?;
+        }
+        "attack_age" => {
+            spec.attack_age = value.as_u64().ok_or_else(|| err("an integer"))? as u32;
+        }
+        "ager_prop" => {
+            spec.ager_prop = value.as_f64().ok_or_else(|| err("a number"))?;
+        }
+        "flood_power" => {
+            spec.flood_power = value.as_f64().ok_or_else(|| err("a number"))?;
+        }
+        "reset_cost" => {
+            spec.reset_cost = value.as_f64().ok_or_else(|| err("a number"))?;
+        }
+        "budget" => {
+            spec.budget = Some(value.as_f64().ok_or_else(|| err("a number of node-days"))?);
+        }
+        "repetitions" => {
+            spec.repetitions.batch = value.as_u64().ok_or_else(|| err("an integer"))? as u32;
+        }
+        "precision" => {
+            spec.repetitions.precision = Some(value.as_f64().ok_or_else(|| err("a number"))?);
+        }
+        "max_trials" => {
+            spec.repetitions.max_trials = value.as_u64().ok_or_else(|| err("an integer"))?;
+        }
+        "max_time" => {
+            spec.repetitions.max_time = Some(value.as_f64()
+                .ok_or_else(|| err("a number of seconds"))?);
+        }
+        "arrival" => {
+            let profiles = match *value {
+                Json::Array(ref v) => v.iter().map(|a| a.as_string()).collect(),
+                ref a => a.as_string().map(|a| vec![a]),
+            };
+            let profiles = profiles.ok_or_else(|| err("a profile name (or a list)"))?;
+            spec.arrival = profiles.into_iter()
+                .map(Arrival::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{}: {}", key, e))?;
+        }
+        "back_join_schedule" => {
+            let s = value.as_string().ok_or_else(|| err("a schedule"))?;
+            spec.add_schedule = Schedule::parse(s).map_err(|e| format!("{}: {}", key, e))?;
+        }
+        "leave_schedule" => {
+            let s = value.as_string().ok_or_else(|| err("a schedule"))?;
+            spec.leave_schedule = Schedule::parse(s).map_err(|e| format!("{}: {}", key, e))?;
+        }
+        "nodes" => spec.nodes = sample_points(key, value)?,
+        "attacking" => spec.attacking = sample_points(key, value)?,
+        "max_join" => spec.max_join = sample_points(key, value)?,
+        "back_join" => spec.add_good = sample_points(key, value)?,
+        "leave_good" => spec.leave_good = sample_points(key, value)?,
+        "min_group" => spec.group_size = sample_points(key, value)?,
+        "quorum_prop" => spec.quorum = sample_points(key, value)?,
+        "age_cap" => {
+            spec.age_cap = value.as_u64().ok_or_else(|| err("an integer"))? as u32;
+        }
+        "elders" => spec.elders = sample_points(key, value)?,
+        "age_quorum_prop" => spec.age_quorum_prop = Some(sample_points(key, value)?),
+        "proof_time" => spec.proof_time = sample_points(key, value)?,
+        "max_days" => spec.max_days = sample_points(key, value)?,
+        _ => return Err(format!("unknown key '{}'", key)),
+    }
+    Ok(())
+}
+
+// Get a list of values from a name or list of names, each of which may select several values.
+fn names<T, F>(value: &Json, f: F) -> Option<Vec<T>>
+    where F: Fn(&str) -> Option<Vec<T>>
+{
+    match *value {
+        Json::String(ref s) => f(s),
+        Json::Array(ref v) => {
+            let mut result = vec![];
+            for name in v {
+                result.extend(f(name.as_string()?)?);
+            }
+            Some(result)
+        }
+        _ => None,
+    }
+}
+
+// Read sample points from a number, a string (using command-line syntax) or a list of these.
+fn sample_points<T>(key: &str, value: &Json) -> Result<SamplePoints<T>, String>
+    where T: FromStr + Copy + AddAssign + PartialOrd<T>
+{
+    let parse = |x: &Json| -> Result<SamplePoints<T>, String> {
+        let s = scalar_str(x).map_err(|e| format!("{}: {}", key, e))?;
+        s.parse().map_err(|e: ParseError| format!("{}: {}", key, e))
+    };
+    match *value {
+        Json::Array(ref v) => {
+            let mut list = vec![];
+            for x in v {
+                match parse(x)? {
+                    SamplePoints::Number(n) => list.push(n),
+                    _ => return Err(format!("{}: expected a list of single values", key)),
+                }
+            }
+            Ok(SamplePoints::List(list))
+        }
+        ref x => parse(x),
+    }
+}
+
+fn scalar_str(value: &Json) -> Result<String, String> {
+    match *value {
+        Json::String(ref s) => Ok(s.clone()),
+        Json::I64(_) | Json::U64(_) | Json::F64(_) => Ok(value.to_string()),
+        _ => Err(format!("expected a number or string, found {}", value)),
+    }
+}
+
+#[test]
+fn test_parse_experiment() {
+    let text = r#"
+        seed = 7
+
+        [[scenario]]
+        name = "a"
+        quorum_alg = "all"
+        min_group = [8, 10]
+        attacking = "10%-20%:10%"
+
+        [[scenario]]
+        name = "b"
+        repetitions = 10
+        points = [ { nodes = 100, attacking = 5 }, { nodes = 200, quorum_prop = "0.5,0.6" } ]
+    "#;
+    let table = toml::Parser::new(text).parse().expect("valid toml");
+    let experiment = parse_experiment(&toml_to_json(toml::Value::Table(table))).expect("valid");
+    assert_eq!(experiment.seed, Some(7));
+    let params = experiment.params;
+    // a: 8 quorum algs * 2 group sizes * 2 attacking; b: 1 + 2 points
+    assert_eq!(params.len(), 32 + 3);
+    assert!(params[..32].iter().all(|p| p.scenario.as_deref() == Some("a")));
+    assert_eq!(params[32].num_initial, 100);
+    assert_eq!(params[32].repetitions.batch, 10);
+    assert_eq!(params[33].num_initial, 200);
+    assert_eq!(params[34].quorum_prop, 0.6);
+
+    let bad = Json::from_str(r#"{"scenario": [{"name": "x", "nodez": 5}]}"#).expect("json");
+    assert!(parse_experiment(&bad).is_err());
+    // Values are checked as on the command line, including in points
+    for bad in [r#"{"scenario": [{"name": "x", "ager_prop": 2}]}"#,
+                r#"{"scenario": [{"name": "x", "precision": 0.1, "max_time": -1}]}"#,
+                r#"{"scenario": [{"name": "x", "precision": 0.1, "max_trials": 0}]}"#,
+                r#"{"scenario": [{"name": "x", "repetitions": 0}]}"#,
+                r#"{"scenario": [{"name": "x", "points": [{"flood_power": -1}]}]}"#] {
+        assert!(parse_experiment(&Json::from_str(bad).expect("json")).is_err());
+    }
+}
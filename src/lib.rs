@@ -0,0 +1,449 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Calculations to do with security of routing system
+//!
+//! The main types are:
+//!
+//! *   `Network`, which simulates the group structure of a network, including joining, leaving,
+//!     relocation of nodes (node ageing), and group splits and merges
+//! *   `Quorum` implementations, which decide whether a group is disrupted or compromised
+//! *   `AttackStrategy` implementations, which decide how malicious nodes behave
+//! *   `Tool` implementations, which calculate or simulate the probabilities of disruption and
+//!     compromise given some `ToolArgs`
+//!
+//! `ToolArgs` can be created directly with `ToolArgsBuilder`, or from a `SimParams` (which uses
+//! the units of the command-line interface). For example:
+//!
+//! ```
+//! use routing_sims::ToolArgsBuilder;
+//! use routing_sims::attack::UntargettedAttack;
+//! use routing_sims::quorum::SimpleQuorum;
+//! use routing_sims::tools::{FullSimTool, Repetitions, Tool};
+//!
+//! let args = ToolArgsBuilder::new()
+//!     .num_initial(100)
+//!     .num_attacking(10)
+//!     .max_steps(20)
+//!     .seed(1)
+//!     .build();
+//! let tool = FullSimTool::new(&args, SimpleQuorum::new(), UntargettedAttack);
+//! let result = tool.calc_p_compromise(&Repetitions::fixed(10));
+//! assert!(result.p_compromise() <= result.p_disrupt());
+//! ```
+
+extern crate rand;
+extern crate rustc_serialize;
+#[macro_use]
+extern crate log;
+extern crate rayon;
+extern crate toml;
+
+pub mod prob;
+pub mod node;
+pub mod net;
+pub mod params;
+pub mod quorum;
+pub mod tools;
+pub mod attack;
+pub mod output;
+pub mod experiment;
+pub mod arrival;
+pub mod schedule;
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use arrival::Arrival;
+use schedule::Schedule;
+use params::{SimParams, RelOrAbs};
+
+
+// We could use templating but there's no reason not to do the easy thing and
+// fix types.
+
+pub type NN = u64;
+pub type RR = f64;
+
+/// Arguments used by tools, in simulation units (nodes and probabilities per step).
+///
+/// Create with `ToolArgsBuilder` or `ToolArgs::from_params`.
+#[derive(Clone, Debug)]
+pub struct ToolArgs {
+    // number initial
+    num_initial: NN,
+    // number malicious added during the attack, and how they arrive
+    num_attacking: NN,
+    arrival: Arrival,
+    // maximum number joining (nodes per step)
+    max_join_rate: RR,
+    // background rate of new good nodes during attack (nodes per step)
+    add_rate_good: RR,
+    // leave rate of good nodes (probability each node leaving per step)
+    leave_rate_good: RR,
+    // factors applied to the above over the days of the attack
+    add_schedule: Schedule,
+    leave_schedule: Schedule,
+    min_group_size: NN,
+    quorum_prop: RR,
+    // proportion of age required by age quorums, if not the same as quorum_prop
+    age_quorum_prop: Option<RR>,
+    max_steps: NN,
+    // length of a step (days)
+    step_len: RR,
+    // proof-of-work the attacker can do in total (node-days), if limited
+    attack_budget: Option<RR>,
+    // seed for all random number generators used with these parameters
+    seed: NN,
+}
+
+/// Reason why a set of arguments cannot be used.
+///
+/// Rates are given per step; command-line options which set the values are named in messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgsError {
+    /// Quorum proportion is not in the range [0, 1]
+    QuorumProp(RR),
+    /// Age quorum proportion is not in the range [0, 1]
+    AgeQuorumProp(RR),
+    /// Step length (proof time) is not positive
+    StepLen(RR),
+    /// Maximum join rate is not greater than the background join rate of good nodes
+    JoinNotAboveAdd(RR, RR),
+    /// Maximum join rate is not greater than the leave rate of good nodes
+    JoinNotAboveLeave(RR, RR),
+    /// Proportion of malicious nodes kept to age is not in the range [0, 1]
+    AgerProp(RR),
+    /// Flood attacker's work rate is negative
+    FloodPower(RR),
+    /// Cost of a flood identity is not positive
+    ResetCost(RR),
+    /// Attacker budget is negative
+    Budget(RR),
+    /// Number of elders is zero
+    NoElders,
+    /// Precision (confidence interval width) is not in the range (0, 1]
+    Precision(RR),
+    /// Number of repetitions (per batch) is zero
+    EmptyBatch,
+    /// Maximum number of repetitions is zero
+    NoTrials,
+    /// Maximum time per parameter set is negative or not finite
+    MaxTime(RR),
+}
+
+impl Display for ArgsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ArgsError::QuorumProp(q) => {
+                write!(f, "quorum proportion (-q) must be between 0 and 1, found {}", q)
+            }
+            ArgsError::AgeQuorumProp(q) => {
+                write!(f,
+                       "age quorum proportion (--agequorum) must be between 0 and 1, found {}",
+                       q)
+            }
+            ArgsError::StepLen(t) => {
+                write!(f, "proof time (--prooftime) must be positive, found {}", t)
+            }
+            ArgsError::JoinNotAboveAdd(join, add) => {
+                write!(f,
+                       "max join rate (-j; {} nodes/step) must be greater than background join \
+                        rate (-b; {} nodes/step)",
+                       join,
+                       add)
+            }
+            ArgsError::JoinNotAboveLeave(join, leave) => {
+                write!(f,
+                       "max join rate (-j; {} nodes/step) must be greater than leave rate (-l; \
+                        {} per step)",
+                       join,
+                       leave)
+            }
+            ArgsError::AgerProp(p) => {
+                write!(f, "proportion of agers (--agers) must be between 0 and 1, found {}", p)
+            }
+            ArgsError::FloodPower(x) => {
+                write!(f, "flood power (--floodpower) must not be negative, found {}", x)
+            }
+            ArgsError::ResetCost(x) => {
+                write!(f, "reset cost (--resetcost) must be positive, found {}", x)
+            }
+            ArgsError::Budget(x) => {
+                write!(f, "attacker budget (--budget) must not be negative, found {}", x)
+            }
+            ArgsError::NoElders => write!(f, "number of elders (--elders) must be at least 1"),
+            ArgsError::Precision(p) => {
+                write!(f,
+                       "precision (--precision) must be greater than 0 and at most 1, found {}",
+                       p)
+            }
+            ArgsError::EmptyBatch => write!(f, "number of repetitions (-p) must be at least 1"),
+            ArgsError::NoTrials => {
+                write!(f, "maximum number of repetitions (--maxtrials) must be at least 1")
+            }
+            ArgsError::MaxTime(t) => {
+                write!(f,
+                       "maximum time (--maxtime) must be a non-negative number of seconds, \
+                        found {}",
+                       t)
+            }
+        }
+    }
+}
+
+impl Error for ArgsError {}
+
+impl ToolArgs {
+    /// Convert from parameters as given on the command line. `seed` is the seed used for this
+    /// parameter set.
+    pub fn from_params(params: &SimParams, seed: NN) -> Result<Self, ArgsError> {
+        let nn = params.num_initial;
+        let nm = params.num_attacking.resolve(nn as RR);
+
+        // Step length in days:
+        let step_len = params.proof_time;
+
+        let max_join = params.max_join.resolve(nn as RR) / step_len;
+        // Convert from num/day to p/step:
+        let add_good = params.add_good.resolve(nn as RR) / step_len;
+        let p_leave = match params.leave_good {
+            RelOrAbs::Rel(r) => r * 0.01,   // number per 100
+            RelOrAbs::Abs(a) => a,
+        };
+        let leave_good = p_leave / step_len;
+
+        ToolArgsBuilder::new()
+            .num_initial(nn)
+            .num_attacking(nm)
+            .arrival(params.arrival.clone())
+            .max_join_rate(max_join)
+            .add_rate_good(add_good)
+            .leave_rate_good(leave_good)
+            .add_schedule(params.add_schedule.clone())
+            .leave_schedule(params.leave_schedule.clone())
+            .min_group_size(params.min_group_size)
+            .quorum_prop(params.quorum_prop)
+            .age_quorum_prop(params.age_quorum_prop)
+            .max_steps((params.max_days / step_len).round() as NN)
+            .step_len(step_len)
+            .attack_budget(params.budget)
+            .seed(seed)
+            .try_build()
+    }
+
+    /// Number of nodes in the initial network (all good)
+    pub fn num_initial(&self) -> NN {
+        self.num_initial
+    }
+    /// Number of malicious nodes added during the attack
+    pub fn num_attacking(&self) -> NN {
+        self.num_attacking
+    }
+    /// How malicious nodes arrive during the attack
+    pub fn arrival(&self) -> &Arrival {
+        &self.arrival
+    }
+    /// Maximum number of nodes joining per step
+    pub fn max_join_rate(&self) -> RR {
+        self.max_join_rate
+    }
+    /// Number of good nodes becoming available to join per step during the attack
+    pub fn add_rate_good(&self) -> RR {
+        self.add_rate_good
+    }
+    /// Probability of each good node leaving per step
+    pub fn leave_rate_good(&self) -> RR {
+        self.leave_rate_good
+    }
+    /// Factor applied to `add_rate_good` over the days of the attack
+    pub fn add_schedule(&self) -> &Schedule {
+        &self.add_schedule
+    }
+    /// Factor applied to `leave_rate_good` over the days of the attack
+    pub fn leave_schedule(&self) -> &Schedule {
+        &self.leave_schedule
+    }
+    /// Minimum group size
+    pub fn min_group_size(&self) -> NN {
+        self.min_group_size
+    }
+    /// Proportion of a group required for quorum
+    pub fn quorum_prop(&self) -> RR {
+        self.quorum_prop
+    }
+    /// Proportion of group age required by quorums using age, if not the same as `quorum_prop`
+    pub fn age_quorum_prop(&self) -> Option<RR> {
+        self.age_quorum_prop
+    }
+    /// Number of steps in the attack before giving up
+    pub fn max_steps(&self) -> NN {
+        self.max_steps
+    }
+    /// Length of a step in days
+    pub fn step_len(&self) -> RR {
+        self.step_len
+    }
+    /// Total proof-of-work the attacker can do (node-days), or `None` if unlimited
+    pub fn attack_budget(&self) -> Option<RR> {
+        self.attack_budget
+    }
+    /// Seed for random number generators
+    pub fn seed(&self) -> NN {
+        self.seed
+    }
+}
+
+/// Builder for `ToolArgs`.
+///
+/// Defaults match those of the command-line interface: 1000 initial nodes, 100 attacking (all
+/// arriving at the start of the attack), 20 joining and 1 good node becoming available per step,
+/// each good node leaving with probability 0.00001 per step (both constant), minimum group size
+/// 10, quorum 0.5 (of both number and age), 100 steps of 1 day, unlimited attacker budget, seed 0.
+pub struct ToolArgsBuilder {
+    args: ToolArgs,
+}
+
+impl ToolArgsBuilder {
+    pub fn new() -> Self {
+        ToolArgsBuilder {
+            args: ToolArgs {
+                num_initial: 1000,
+                num_attacking: 100,
+                arrival: Arrival::Once,
+                max_join_rate: 20.0,
+                add_rate_good: 1.0,
+                leave_rate_good: 0.00001,
+                add_schedule: Schedule::Constant,
+                leave_schedule: Schedule::Constant,
+                min_group_size: 10,
+                quorum_prop: 0.5,
+                age_quorum_prop: None,
+                max_steps: 100,
+                step_len: 1.0,
+                attack_budget: None,
+                seed: 0,
+            },
+        }
+    }
+
+    pub fn num_initial(mut self, n: NN) -> Self {
+        self.args.num_initial = n;
+        self
+    }
+    pub fn num_attacking(mut self, n: NN) -> Self {
+        self.args.num_attacking = n;
+        self
+    }
+    pub fn arrival(mut self, arrival: Arrival) -> Self {
+        self.args.arrival = arrival;
+        self
+    }
+    pub fn max_join_rate(mut self, rate: RR) -> Self {
+        self.args.max_join_rate = rate;
+        self
+    }
+    pub fn add_rate_good(mut self, rate: RR) -> Self {
+        self.args.add_rate_good = rate;
+        self
+    }
+    pub fn leave_rate_good(mut self, p: RR) -> Self {
+        self.args.leave_rate_good = p;
+        self
+    }
+    pub fn add_schedule(mut self, schedule: Schedule) -> Self {
+        self.args.add_schedule = schedule;
+        self
+    }
+    pub fn leave_schedule(mut self, schedule: Schedule) -> Self {
+        self.args.leave_schedule = schedule;
+        self
+    }
+    pub fn min_group_size(mut self, n: NN) -> Self {
+        self.args.min_group_size = n;
+        self
+    }
+    pub fn quorum_prop(mut self, prop: RR) -> Self {
+        self.args.quorum_prop = prop;
+        self
+    }
+    pub fn age_quorum_prop(mut self, prop: Option<RR>) -> Self {
+        self.args.age_quorum_prop = prop;
+        self
+    }
+    pub fn max_steps(mut self, n: NN) -> Self {
+        self.args.max_steps = n;
+        self
+    }
+    pub fn step_len(mut self, days: RR) -> Self {
+        self.args.step_len = days;
+        self
+    }
+    pub fn attack_budget(mut self, budget: Option<RR>) -> Self {
+        self.args.attack_budget = budget;
+        self
+    }
+    pub fn seed(mut self, seed: NN) -> Self {
+        self.args.seed = seed;
+        self
+    }
+
+    /// Check and return the arguments.
+    ///
+    /// Panics if the arguments are unusable; see `try_build`.
+    pub fn build(self) -> ToolArgs {
+        self.try_build().unwrap_or_else(|e| panic!("invalid arguments: {}", e))
+    }
+
+    /// Check and return the arguments, or the reason they are unusable (e.g. the join rate is
+    /// not greater than the rates at which good nodes are added and leave).
+    pub fn try_build(self) -> Result<ToolArgs, ArgsError> {
+        let args = self.args;
+        if !(0.0..=1.0).contains(&args.quorum_prop) {
+            return Err(ArgsError::QuorumProp(args.quorum_prop));
+        }
+        if let Some(prop) = args.age_quorum_prop {
+            if !(0.0..=1.0).contains(&prop) {
+                return Err(ArgsError::AgeQuorumProp(prop));
+            }
+        }
+        if args.step_len <= 0.0 {
+            return Err(ArgsError::StepLen(args.step_len));
+        }
+        if args.max_join_rate <= args.add_rate_good {
+            return Err(ArgsError::JoinNotAboveAdd(args.max_join_rate, args.add_rate_good));
+        }
+        if args.max_join_rate <= args.leave_rate_good {
+            return Err(ArgsError::JoinNotAboveLeave(args.max_join_rate, args.leave_rate_good));
+        }
+        let init_steps = (args.num_initial as RR) / (args.max_join_rate - args.leave_rate_good);
+        if init_steps > 10000.0 {
+            warn!("Join rate ({} nodes/step) - leave rate ({} nodes/step) requires many steps \
+                   for init (estimate: {})",
+                  args.max_join_rate,
+                  args.leave_rate_good,
+                  init_steps.round() as NN);
+        }
+        Ok(args)
+    }
+}
+
+impl Default for ToolArgsBuilder {
+    fn default() -> Self {
+        ToolArgsBuilder::new()
+    }
+}
@@ -15,10 +15,10 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-// Calculations to do with security of routing system
+// Command-line interface to the simulations
 
+extern crate routing_sims;
 extern crate rand;
-extern crate rustc_serialize;
 #[macro_use]
 extern crate clap;
 #[macro_use]
@@ -26,136 +26,169 @@ extern crate log;
 extern crate env_logger;
 extern crate rayon;
 
-mod prob;
-mod node;
-mod net;
 mod args;
-mod quorum;
-mod tools;
-mod attack;
+mod progress;
 
-use std::cmp::max;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
 
+use rand::{thread_rng, Rng};
 use rayon::prelude::*;
-use rayon::par_iter::collect::collect_into;
 
-use args::{ArgProc, SimParams, RelOrAbs};
-
-
-// We could use templating but there's no reason not to do the easy thing and
-// fix types.
-
-pub type NN = u64;
-pub type RR = f64;
-
-pub const PARAM_TITLES: [&'static str; 10] = ["NInitial",
-                                              "NAttack",
-                                              "MaxJoin",
-                                              "BackJoin",
-                                              "PLeave",
-                                              "MinGroup",
-                                              "QuorumProp",
-                                              "MaxSteps",
-                                              "P(disruption)",
-                                              "P(compromise)"];
-pub struct ToolArgs {
-    // number initial
-    num_initial: NN,
-    // number malicious added at start of attack
-    num_attacking: NN,
-    // maximum number joining (nodes per step)
-    max_join_rate: RR,
-    // background rate of new good nodes during attack (nodes per step)
-    add_rate_good: RR,
-    // leave rate of good nodes (probability each node leaving per step)
-    leave_rate_good: RR,
-    min_group_size: NN,
-    quorum_prop: RR,
-    max_steps: NN,
-}
-
-impl ToolArgs {
-    pub fn from_params(params: &SimParams) -> Self {
-        let nn = params.num_initial;
-        let nm = params.num_attacking.from_base(nn as RR);
-
-        // Step length in days:
-        let step_len = params.proof_time;
-
-        assert!(params.quorum_prop >= 0.0 && params.quorum_prop <= 1.0);
-
-        let max_join = params.max_join.from_base(nn as RR) / step_len;
-        // Convert from num/day to p/step:
-        let add_good = params.add_good.from_base(nn as RR) / step_len;
-        let p_leave = match params.leave_good {
-            RelOrAbs::Rel(r) => r * 0.01,   // number per 100
-            RelOrAbs::Abs(a) => a,
-        };
-        let leave_good = p_leave / step_len;
-        assert!(max_join > add_good);
-        assert!(max_join > leave_good);
-        if (nn as RR) / (max_join - leave_good) > 10000.0 {
-            warn!("Join rate ({} nodes/step) - leave rate ({} nodes/step) requires many steps \
-                   for init (estimate: {})",
-                  max_join,
-                  leave_good,
-                  ((nn as RR) / (max_join - leave_good)).round() as NN);
-        }
-
-        ToolArgs {
-            num_initial: nn,
-            num_attacking: nm,
-            max_join_rate: max_join,
-            add_rate_good: add_good,
-            leave_rate_good: leave_good,
-            min_group_size: params.min_group_size,
-            quorum_prop: params.quorum_prop,
-            max_steps: (params.max_days / step_len).round() as NN,
+use routing_sims::{NN, ToolArgs};
+use routing_sims::prob::sub_seed;
+use routing_sims::output::{Format, Writer, Completed, make_row, make_params_row,
+                           make_trace_row};
+use args::ArgProc;
+use progress::Progress;
+
+
+// Open a file for writing, or standard output if no path is given.
+fn open_output(path: Option<&str>) -> Box<dyn Write> {
+    match path {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).unwrap_or_else(|e| {
+                eprintln!("Error: unable to create output file {}: {}", path, e);
+                process::exit(1);
+            })))
         }
+        None => Box::new(io::stdout()),
     }
 }
 
+// Read results from a previous run and open the file for appending. An incomplete last line
+// (from an interrupted write) is removed. Also returns whether the file has any content left
+// (e.g. a header), in which case no header should be written.
+fn open_resume(path: &str, format: Format) -> (Completed, bool, Box<dyn Write>) {
+    let fail = |e: &dyn Display| -> ! {
+        eprintln!("Error: unable to resume from {}: {}", path, e);
+        process::exit(1);
+    };
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .unwrap_or_else(|e| fail(&e));
+    let mut text = String::new();
+    file.read_to_string(&mut text).unwrap_or_else(|e| fail(&e));
+    if !text.is_empty() && !text.ends_with('\n') {
+        let len = text.rfind('\n').map_or(0, |i| i + 1);
+        text.truncate(len);
+        file.set_len(len as u64).unwrap_or_else(|e| fail(&e));
+    }
+    let completed = Completed::read(format, &text).unwrap_or_else(|e| fail(&e));
+    (completed, !text.is_empty(), Box::new(BufWriter::new(file)))
+}
 
 fn main() {
     env_logger::init().unwrap();
 
-    let (repetitions, param_sets) = ArgProc::make_sim_params();
-    // TODO: print number of sims and/or progress
+    let (options, param_sets) = ArgProc::make_sim_params();
+
+    let (completed, has_content, out) = match (options.resume, options.output.as_deref()) {
+        (true, Some(path)) => {
+            let (completed, has_content, out) = open_resume(path, options.format);
+            (Some(completed), has_content, out)
+        }
+        (_, path) => (None, false, open_output(path)),
+    };
+    let seed = options.seed
+        .or_else(|| completed.as_ref().and_then(|c| c.seed()))
+        .unwrap_or_else(|| thread_rng().gen());
+    let mut writer = Writer::new(options.format, out);
+    if has_content {
+        writer.skip_header();
+    }
 
-    info!("Starting to simulate {} different parameter sets",
-          param_sets.len());
-    let mut results = Vec::new();
-    collect_into(param_sets.into_par_iter().map(|item| item.result(repetitions)),
-                 &mut results);
+    if let Some(ref path) = options.trace {
+        if param_sets.len() != 1 {
+            eprintln!("Error: --trace requires a single parameter set (found {})",
+                      param_sets.len());
+            process::exit(1);
+        }
+        let params = &param_sets[0];
+        let mut trace_writer = Writer::new(options.format, open_output(Some(path)));
+        let (args, result) = params.trace(sub_seed(seed, 0), &mut |record| {
+                trace_writer.write_row(&make_trace_row(record)).expect("write trace");
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Error: invalid parameters ({}): {}", params, e);
+                process::exit(1);
+            });
+        trace_writer.flush().expect("write trace");
+        let result = result.unwrap_or_else(|| {
+            eprintln!("Error: tool {} does not support --trace", params.sim_type.name());
+            process::exit(1);
+        });
+        writer.write_row(&make_row(seed, params, &args, &result)).expect("write result");
+        writer.flush().expect("write result");
+        return;
+    }
 
-    //     tool.print_message();
-    let col_widths: Vec<usize> = PARAM_TITLES.iter().map(|name| max(name.len(), 8)).collect();
-    for col in 0..col_widths.len() {
-        print!("{1:<0$}", col_widths[col], PARAM_TITLES[col]);
-        print!(" ");
+    // Each parameter set gets its own seed, derived from its position in the list, so results do
+    // not depend on the order in which they are run (or on other sets being skipped).
+    let total = param_sets.len();
+    let mut jobs = Vec::with_capacity(total);
+    let mut n_done = 0;
+    for (i, params) in param_sets.into_iter().enumerate() {
+        match ToolArgs::from_params(&params, sub_seed(seed, i as NN)) {
+            Ok(args) => {
+                let row = make_params_row(seed, &params, &args);
+                if completed.as_ref().is_some_and(|c| c.contains(&row)) {
+                    n_done += 1;
+                } else {
+                    jobs.push((params, args));
+                }
+            }
+            Err(e) => eprintln!("Skipping invalid parameter set ({}): {}", params, e),
+        }
+    }
+    if jobs.len() + n_done < total {
+        eprintln!("Skipped {} of {} parameter sets", total - jobs.len() - n_done, total);
+        if jobs.is_empty() && n_done == 0 {
+            process::exit(1);
+        }
+    }
+    if completed.is_some() {
+        eprintln!("Resuming with seed {}: {} of {} parameter sets already have results",
+                  seed,
+                  n_done,
+                  total);
     }
-    println!("");
 
-    for (args, result) in results {
-        print!("{1:<0$}", col_widths[0], args.num_initial);
-        print!(" ");
-        print!("{1:<0$}", col_widths[1], args.num_attacking);
-        print!(" ");
-        print!("{1:<0$}", col_widths[2], args.max_join_rate);
-        print!(" ");
-        print!("{1:<0$}", col_widths[3], args.add_rate_good);
-        print!(" ");
-        print!("{1:<0$}", col_widths[4], args.leave_rate_good);
-        print!(" ");
-        print!("{1:<0$}", col_widths[5], args.min_group_size);
-        print!(" ");
-        print!("{1:<.*}", col_widths[6] - 2, args.quorum_prop);
-        print!(" ");
-        print!("{1:<.*}", col_widths[7] - 2, args.max_steps);
-        print!(" ");
-        print!("{1:<.*}", col_widths[8] - 2, result.p_disrupt());
-        print!(" ");
-        print!("{1:<.*}", col_widths[9] - 2, result.p_compromise());
-        println!("");
+    info!("Starting to simulate {} different parameter sets with seed {}",
+          jobs.len(),
+          seed);
+    let show_progress = Progress::wanted();
+    if show_progress {
+        eprintln!("Simulating {} parameter sets with seed {}", jobs.len(), seed);
     }
+    let progress = Progress::new(jobs.len());
+    // Results are written (in order of completion) as soon as they are available, so that an
+    // interrupted run keeps what it has done and can be resumed.
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        if show_progress {
+            scope.spawn(|| progress.display());
+        }
+        let progress = &progress;
+        scope.spawn(move || {
+            jobs.into_par_iter().for_each(|(params, args)| {
+                let result = params.run(&args, Some(&progress.reps_done));
+                progress.sets_done.fetch_add(1, Ordering::Relaxed);
+                sender.send((params, args, result)).expect("send result");
+            });
+        });
+        for (params, args, result) in receiver {
+            writer.write_row(&make_row(seed, &params, &args, &result)).expect("write result");
+            writer.flush().expect("write result");
+        }
+        progress.finish();
+    });
 }
@@ -23,25 +23,46 @@
 //! *   Node names are simply random numbers
 //! *   Node leaving and group merging are not simulated
 
-use std::collections::hash_map::{HashMap, Entry};
+use std::cmp::{min, max};
+use std::collections::hash_map::{HashMap, Entry, DefaultHasher};
+use std::hash::BuildHasherDefault;
 use std::mem;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use {NN, RR, ToolArgs};
 use attack::AttackStrategy;
 use node::{Prefix, NodeName, NodeData, new_node_name};
+use prob::SimRng;
 
 
 #[allow(non_snake_case)]
-fn sample_NN() -> NN {
-    thread_rng().gen()
+fn sample_NN(rng: &mut SimRng) -> NN {
+    rng.gen()
+}
+
+/// Hasher used for all maps in the network.
+///
+/// Unlike the default `RandomState` this is not randomised per map, thus iteration order (which
+/// affects which nodes get relocated and the order of random draws) is reproducible.
+pub type DetHasher = BuildHasherDefault<DefaultHasher>;
+
+// Call `on_merge` for all malicious nodes of a group being merged.
+fn notify_merge(attack: &mut dyn AttackStrategy,
+                old_prefix: Prefix,
+                new_prefix: Prefix,
+                group: &Group) {
+    for (name, data) in group {
+        if data.is_malicious() {
+            attack.on_merge(old_prefix, new_prefix, *name, data);
+        }
+    }
 }
 
 /// Controls whether a node can get added to a group
 pub trait AddRestriction {
     /// May prevent add operation, for example if the group has too many nodes of this age.
-    fn can_add(_node_data: &NodeData, _group: &HashMap<NodeName, NodeData>) -> bool {
+    fn can_add(_node_data: &NodeData, _group: &Group) -> bool {
         true
     }
 }
@@ -53,7 +74,7 @@ impl AddRestriction for NoAddRestriction {}
 /// Limit the number of nodes of certain ages (currently only ages 0 and 1)
 pub struct RestrictOnePerAge;
 impl AddRestriction for RestrictOnePerAge {
-    fn can_add(node_data: &NodeData, group: &HashMap<NodeName, NodeData>) -> bool {
+    fn can_add(node_data: &NodeData, group: &Group) -> bool {
         let age = node_data.age();
         if age > 1 {
             return true;
@@ -62,8 +83,35 @@ impl AddRestriction for RestrictOnePerAge {
     }
 }
 
+/// Summary of the state of a network, e.g. for tracing.
+#[derive(Clone, Copy, Debug)]
+pub struct NetStats {
+    /// Number of groups
+    pub groups: usize,
+    /// Size of the smallest group
+    pub min_group_size: usize,
+    /// Mean group size
+    pub mean_group_size: RR,
+    /// Size of the largest group
+    pub max_group_size: usize,
+    /// Number of good nodes in groups
+    pub good: usize,
+    /// Number of malicious nodes in groups
+    pub malicious: usize,
+    /// Largest proportion of malicious nodes in any group
+    pub worst_malicious_fraction: RR,
+    /// Number of good nodes waiting to join
+    pub avail_good: NN,
+    /// Number of malicious nodes waiting to join
+    pub avail_malicious: NN,
+    /// Number of nodes doing proof-of-work, to be added next step
+    pub pending: usize,
+    /// Work spent by the attacker so far (node-days)
+    pub attack_spent: RR,
+}
+
 /// A `Group` is a collection of named nodes.
-pub type Group = HashMap<NodeName, NodeData>;
+pub type Group = HashMap<NodeName, NodeData, DetHasher>;
 
 /// A `Network` is a collection of groups.
 ///
@@ -71,35 +119,46 @@ pub type Group = HashMap<NodeName, NodeData>;
 /// to simulate network creation.
 pub struct Network {
     min_group_size: usize,
-    groups: HashMap<Prefix, Group>,
+    groups: HashMap<Prefix, Group, DetHasher>,
     // Number of new nodes allowed, and probability of a good node leaving.
     // These are accumulated between steps, not simply reset each step.
     to_join: RR,
     p_leave: RR,
+    // factor applied to the leave rate of good nodes
+    leave_factor: RR,
     // Number of new nodes available (good and malicious):
     avail_good: NN,
     avail_malicious: NN,
     // nodes pending joining a group this step, and those joining next step:
     pending_nodes: Vec<(NodeName, NodeData)>,
     pending_next: Vec<(NodeName, NodeData)>,
+    // proof-of-work done by malicious nodes (node-days), and whether the attacker has run out
+    attack_spent: RR,
+    attack_stopped: bool,
+    // source of all randomness in the network
+    rng: SimRng,
 }
 
 impl Network {
-    /// Create. Specify minimum group size.
+    /// Create. Specify minimum group size and the random number generator to use.
     ///
     /// An initial, empty, group is created.
-    pub fn new(min_group_size: usize) -> Self {
-        let mut groups = HashMap::new();
-        groups.insert(Prefix::new(0, 0), HashMap::new());
+    pub fn new(min_group_size: usize, rng: SimRng) -> Self {
+        let mut groups = HashMap::default();
+        groups.insert(Prefix::new(0, 0), Group::default());
         Network {
-            min_group_size: min_group_size,
-            groups: groups,
+            min_group_size,
+            groups,
             to_join: 0.0,
             p_leave: 0.0,
+            leave_factor: 1.0,
             avail_good: 0,
             avail_malicious: 0,
             pending_nodes: vec![],
             pending_next: vec![],
+            attack_spent: 0.0,
+            attack_stopped: false,
+            rng,
         }
     }
 
@@ -114,14 +173,36 @@ impl Network {
         self.avail_malicious += n_malicious;
     }
 
+    /// Multiply the leave rate of good nodes (from `ToolArgs`) by `factor` from now on.
+    pub fn set_leave_factor(&mut self, factor: RR) {
+        self.leave_factor = factor;
+    }
+
+    /// Proof-of-work done by malicious nodes so far (node-days)
+    pub fn attack_spent(&self) -> RR {
+        self.attack_spent
+    }
+
+    /// Whether the attacker has exhausted its budget (`ToolArgs::attack_budget`)
+    pub fn attack_stopped(&self) -> bool {
+        self.attack_stopped
+    }
+
     /// Run a step in the simulation.
     ///
+    /// Each malicious join attempt costs the attacker one proof time of work (scaled by the
+    /// strategy's `identity_cost`). This includes resets and relocated or rejected nodes, since
+    /// these must try to join again. Once the attacker cannot afford an attempt it stops: no more
+    /// malicious nodes join, rejoin or reset.
+    ///
     /// Note: if a node has done proof-of-work but its original target group splits, it
     /// simply joins whichever group it would now be in. If a node has done proof of work and
     /// is not accepted due to age restrictions, it is given a new name and must redo work.
-    pub fn do_step<AR: AddRestriction>(&mut self, args: &ToolArgs, attack: &mut AttackStrategy) {
+    pub fn do_step<AR: AddRestriction>(&mut self, args: &ToolArgs, attack: &mut dyn AttackStrategy) {
         self.to_join += args.max_join_rate;
-        self.p_leave += args.leave_rate_good;
+        self.p_leave += args.leave_rate_good * self.leave_factor;
+
+        let identity_cost = args.step_len() * attack.identity_cost();
 
         // Add any nodes which were waiting for proof-of-work to complete
         while let Some((node_name, node_data)) = self.pending_nodes.pop() {
@@ -134,22 +215,38 @@ impl Network {
                     // group; however, either that was an old group which just got a new
                     // member, or it is a split result with at least one node more than the
                     // minimum number. Either way merging is not required.
-                    self.churn(prefix, node_name).map(|(old_name, data)| (Some(old_name), data))
+                    let moved = self.churn(prefix, node_name);
+                    if let Some((old_name, ref data)) = moved {
+                        if data.is_malicious() {
+                            attack.on_relocate(prefix, old_name, data);
+                        }
+                    }
+                    attack.on_churn(self, prefix, node_name);
+                    moved.map(|(old_name, data)| (Some(old_name), data))
+                }
+                Err(node_data) => {
+                    if node_data.is_malicious() {
+                        attack.on_rejected(self, node_name, &node_data);
+                    }
+                    Some((None, node_data))
                 }
-                Err(node_data) => Some((None, node_data)),
             };
-            if let Some((opt_old_name, data)) = opt_moved {
-                let new_name = new_node_name();
-                if data.is_malicious() &&
-                   attack.reset_on_new_name(self, opt_old_name, new_name, &data) {
+            if let Some((opt_old_name, mut data)) = opt_moved {
+                let new_name = new_node_name(&mut self.rng);
+                if data.is_malicious() && !self.attack_stopped &&
+                   attack.reset_on_new_name(self, opt_old_name, new_name, &mut data) {
                     // Node resets: drop data, but remember that we need another malicious node
                     self.avail_malicious += 1;
-                } else {
+                } else if !data.is_malicious() || self.charge_attack(args, identity_cost) {
                     self.pending_next.push((new_name, data));
                 }
             }
         }
 
+        if !self.attack_stopped {
+            self.avail_malicious += attack.new_identities(self);
+        }
+
         // Add new nodes, up to the maximum allowed this step; these do not get inserted until
         // next step.
         while self.to_join >= 1.0 {
@@ -164,14 +261,19 @@ impl Network {
                 (0, _g) => false,
                 (m, g) => {
                     let p = (m as RR) / ((m + g) as RR);
-                    let thresh = (p * (NN::max_value() as RR)).round() as NN;
-                    sample_NN() < thresh
+                    let thresh = (p * (NN::MAX as RR)).round() as NN;
+                    sample_NN(&mut self.rng) < thresh
                 }
             };
-            let new_name = new_node_name();
-            let data = NodeData::new(is_malicious);
+            if is_malicious && !self.charge_attack(args, identity_cost) {
+                // The attacker has stopped: choose again from the good nodes
+                self.to_join += 1.0;
+                continue;
+            }
+            let new_name = new_node_name(&mut self.rng);
+            let mut data = NodeData::new(is_malicious);
 
-            if is_malicious && attack.reset_on_new_name(self, None, new_name, &data) {
+            if is_malicious && attack.reset_on_new_name(self, None, new_name, &mut data) {
                 // Attacking node resets: let a new one replace it. The only thing which changed is
                 // that self.to_join has been decremented.
                 continue;
@@ -188,17 +290,71 @@ impl Network {
         // only do anything if probability is significant, otherwise accumulate
         if self.p_leave >= 0.001 {
             let p_leave = self.p_leave;
-            let n = self.probabilistic_drop(p_leave) as NN;
+            let n = self.probabilistic_drop(p_leave, attack) as NN;
             // Add replacements to maintain size. Note that only good nodes leave like this.
             self.avail_good += n;
             self.p_leave = 0.0;
         }
 
+        // Malicious nodes leave only when the attacker chooses
+        let leaving = attack.choose_leaving(self);
+        if !leaving.is_empty() {
+            self.withdraw_malicious(&leaving, attack);
+        }
+
         mem::swap(&mut self.pending_nodes, &mut self.pending_next);
+        attack.on_step(self);
+    }
+
+    // Charge the attacker for a join attempt, if it can afford it; otherwise stop the attack.
+    fn charge_attack(&mut self, args: &ToolArgs, cost: RR) -> bool {
+        if args.attack_budget().is_some_and(|b| self.attack_spent + cost > b) {
+            self.attack_stopped = true;
+            self.avail_malicious = 0;
+            false
+        } else {
+            self.attack_spent += cost;
+            true
+        }
+    }
+
+    /// Calculate summary statistics
+    pub fn stats(&self) -> NetStats {
+        let mut stats = NetStats {
+            groups: self.groups.len(),
+            min_group_size: usize::MAX,
+            mean_group_size: 0.0,
+            max_group_size: 0,
+            good: 0,
+            malicious: 0,
+            worst_malicious_fraction: 0.0,
+            avail_good: self.avail_good,
+            avail_malicious: self.avail_malicious,
+            pending: self.pending_nodes.len(),
+            attack_spent: self.attack_spent,
+        };
+        for group in self.groups.values() {
+            let malicious = group.values().filter(|data| data.is_malicious()).count();
+            stats.min_group_size = min(stats.min_group_size, group.len());
+            stats.max_group_size = max(stats.max_group_size, group.len());
+            stats.good += group.len() - malicious;
+            stats.malicious += malicious;
+            if !group.is_empty() {
+                let fraction = malicious as RR / group.len() as RR;
+                stats.worst_malicious_fraction = stats.worst_malicious_fraction.max(fraction);
+            }
+        }
+        stats.mean_group_size = (stats.good + stats.malicious) as RR / stats.groups as RR;
+        stats
+    }
+
+    /// Minimum group size: groups smaller than this get merged
+    pub fn min_group_size(&self) -> usize {
+        self.min_group_size
     }
 
     /// Access groups
-    pub fn groups(&self) -> &HashMap<Prefix, HashMap<NodeName, NodeData>> {
+    pub fn groups(&self) -> &HashMap<Prefix, Group, DetHasher> {
         &self.groups
     }
 
@@ -226,7 +382,7 @@ impl Network {
                                         node_data: NodeData)
                                         -> Result<Prefix, NodeData> {
         let prefix = self.find_prefix(node_name);
-        let mut group = self.groups.get_mut(&prefix).expect("network must include all groups");
+        let group = self.groups.get_mut(&prefix).expect("network must include all groups");
         if group.len() > self.min_group_size && !AR::can_add(&node_data, group) {
             return Err(node_data);
         }
@@ -241,14 +397,15 @@ impl Network {
 
     /// Probabilistically drop good nodes (`p` is the chance of each node being dropped).
     /// Return the number of nodes dropped.
-    pub fn probabilistic_drop(&mut self, p: RR) -> usize {
-        let thresh = (p * (NN::max_value() as RR)).round() as NN;
+    pub fn probabilistic_drop(&mut self, p: RR, attack: &mut dyn AttackStrategy) -> usize {
+        let thresh = (p * (NN::MAX as RR)).round() as NN;
         let mut need_merge = vec![];
         let mut num = 0;
+        let rng = &mut self.rng;
         for (prefix, ref mut group) in &mut self.groups {
             let to_remove: Vec<_> = group.iter()
-                .filter_map(|(ref key, ref data)| if !data.is_malicious() && sample_NN() < thresh {
-                    Some(**key)
+                .filter_map(|(key, data)| if !data.is_malicious() && sample_NN(rng) < thresh {
+                    Some(*key)
                 } else {
                     None
                 })
@@ -263,6 +420,40 @@ impl Network {
         }
 
         // Do any merges needed (after all removals)
+        self.merge_groups(need_merge, attack);
+
+        num
+    }
+
+    /// Remove the named malicious nodes from their groups, merging groups as necessary, and add
+    /// a replacement to the queue of available malicious nodes for each. Names not found or of
+    /// good nodes are ignored. Returns the number of nodes removed.
+    pub fn withdraw_malicious(&mut self,
+                              names: &[NodeName],
+                              attack: &mut dyn AttackStrategy)
+                              -> usize {
+        let mut need_merge = vec![];
+        let mut num = 0;
+        for name in names {
+            let prefix = self.find_prefix(*name);
+            let group = self.groups.get_mut(&prefix).expect("network must include all groups");
+            if !group.get(name).is_some_and(|data| data.is_malicious()) {
+                continue;
+            }
+            group.remove(name);
+            num += 1;
+            if group.len() < self.min_group_size {
+                need_merge.push(prefix);
+            }
+        }
+        self.avail_malicious += num as NN;
+        self.merge_groups(need_merge, attack);
+        num
+    }
+
+    // Merge each group listed (if it still exists) with its sibling and all descendants of its
+    // parent.
+    fn merge_groups(&mut self, mut need_merge: Vec<Prefix>, attack: &mut dyn AttackStrategy) {
         while let Some(prefix) = need_merge.pop() {
             if prefix.bit_count() == 0 {
                 // Not enough members in network yet; nothing we can do
@@ -276,17 +467,17 @@ impl Network {
                 }
             };
             let parent = prefix.popped();
+            notify_merge(attack, prefix, parent, &group);
             // Groups are disjoint, so all "compatibles" should be descendents of the new "parent"
             let compatible_prefixes: Vec<_> =
                 self.groups.keys().filter(|k| k.is_compatible(parent)).cloned().collect();
             for p in compatible_prefixes {
                 let other_group = self.groups.remove(&p).expect("has group");
+                notify_merge(attack, p, parent, &other_group);
                 group.extend(other_group);
             }
             self.groups.insert(parent, group);
         }
-
-        num
     }
 
     /// Check need_split and if true call do_split. Return the prefix matching
@@ -294,7 +485,7 @@ impl Network {
     pub fn maybe_split(&mut self,
                        prefix: Prefix,
                        name: NodeName,
-                       attack: &mut AttackStrategy)
+                       attack: &mut dyn AttackStrategy)
                        -> Prefix {
         if !self.need_split(prefix) {
             return prefix;
@@ -318,7 +509,7 @@ impl Network {
     }
 
     /// Do a split. Return prefixes of new groups.
-    pub fn do_split(&mut self, prefix: Prefix, attack: &mut AttackStrategy) -> (Prefix, Prefix) {
+    pub fn do_split(&mut self, prefix: Prefix, attack: &mut dyn AttackStrategy) -> (Prefix, Prefix) {
         let old_group = match self.groups.remove(&prefix) {
             Some(g) => g,
             None => {
@@ -359,17 +550,16 @@ impl Network {
     /// On relocation, the node is returned (with its old name); the driver should
     /// create a new name and call add_node with the new name.
     pub fn churn(&mut self, prefix: Prefix, new_node: NodeName) -> Option<(NodeName, NodeData)> {
-        let mut group = self.groups.get_mut(&prefix).expect("churn called with invalid group");
+        let group = self.groups.get_mut(&prefix).expect("churn called with invalid group");
         // Increment churn counters and see if any is ready to be relocated.
         let mut to_relocate: Option<(NodeName, u32)> = None;
         for (node_name, ref mut node_data) in group.iter_mut() {
             if *node_name == new_node {
                 continue;   // skip this node
             }
-            if node_data.churn_and_can_age() {
-                if to_relocate.map_or(true, |n| node_data.churns() > n.1) {
-                    to_relocate = Some((*node_name, node_data.churns()));
-                }
+            if node_data.churn_and_can_age() &&
+               to_relocate.is_none_or(|n| node_data.churns() > n.1) {
+                to_relocate = Some((*node_name, node_data.churns()));
             }
         }
         let to_relocate = match to_relocate {
@@ -398,3 +588,113 @@ impl Network {
         self.min_group_size + 1
     }
 }
+
+#[test]
+fn test_withdraw_malicious() {
+    use attack::UntargettedAttack;
+    use prob::make_rng;
+
+    let mut net = Network::new(2, make_rng(0));
+    let mut attack = UntargettedAttack;
+    // Three nodes in each half of the name space; two malicious nodes in the second half
+    for i in [0, 1, 2, 4, 5, 6] {
+        let name: NodeName = i << 61;
+        let prefix = net.add_node::<NoAddRestriction>(name, NodeData::new(i >= 5)).expect("add");
+        net.maybe_split(prefix, name, &mut attack);
+    }
+    assert_eq!(net.groups().len(), 2);
+
+    // Good nodes and unknown names are ignored
+    let removed = net.withdraw_malicious(&[0, 5 << 61, 6 << 61, 7 << 61], &mut attack);
+    assert_eq!(removed, 2);
+    // The second group is too small, so is merged
+    let stats = net.stats();
+    assert_eq!(stats.groups, 1);
+    assert_eq!((stats.good, stats.malicious), (4, 0));
+    assert_eq!(stats.avail_malicious, 2);
+}
+
+#[test]
+fn test_attack_budget() {
+    use attack::UntargettedAttack;
+    use prob::make_rng;
+    use ToolArgsBuilder;
+
+    // Budget for three join attempts of one day each
+    let args = ToolArgsBuilder::new().max_join_rate(5.0).attack_budget(Some(3.5)).build();
+    let mut net = Network::new(2, make_rng(0));
+    net.add_avail(20, 20);
+    for _ in 0..10 {
+        net.do_step::<NoAddRestriction>(&args, &mut UntargettedAttack);
+    }
+    // Three malicious nodes joined (or rejoined after relocation) before the attack stopped,
+    // while all good nodes could still join
+    assert!(net.attack_stopped());
+    assert_eq!(net.attack_spent(), 3.0);
+    let stats = net.stats();
+    assert!(stats.malicious <= 3);
+    assert_eq!((stats.avail_good, stats.avail_malicious), (0, 0));
+}
+
+#[test]
+fn test_attack_hooks() {
+    use attack::{AttackStrategy, UntargettedAttack};
+    use prob::make_rng;
+    use ToolArgsBuilder;
+
+    // Records the arguments of every hook the network calls
+    #[derive(Default)]
+    struct Recorder {
+        merges: Vec<(Prefix, Prefix, NodeName)>,
+        relocations: Vec<(Prefix, NodeName, NodeData)>,
+        rejections: Vec<NodeData>,
+        steps: usize,
+    }
+    impl AttackStrategy for Recorder {
+        fn on_merge(&mut self,
+                    old_prefix: Prefix,
+                    new_prefix: Prefix,
+                    node_name: NodeName,
+                    _node_data: &NodeData) {
+            self.merges.push((old_prefix, new_prefix, node_name));
+        }
+        fn on_relocate(&mut self, old_prefix: Prefix, old_name: NodeName, node_data: &NodeData) {
+            self.relocations.push((old_prefix, old_name, *node_data));
+        }
+        fn on_rejected(&mut self, _net: &Network, _node_name: NodeName, node_data: &NodeData) {
+            self.rejections.push(*node_data);
+        }
+        fn on_step(&mut self, _net: &Network) {
+            self.steps += 1;
+        }
+    }
+
+    // Merges are reported for malicious nodes of both merged groups, with the parent prefix
+    let mut net = Network::new(2, make_rng(0));
+    let mut rec = Recorder::default();
+    for i in [0, 1, 2, 4, 5, 6] {
+        let name: NodeName = i << 61;
+        let malicious = i == 1 || i >= 5;
+        let prefix = net.add_node::<NoAddRestriction>(name, NodeData::new(malicious)).expect("add");
+        net.maybe_split(prefix, name, &mut UntargettedAttack);
+    }
+    net.withdraw_malicious(&[5 << 61, 6 << 61], &mut rec);
+    assert_eq!(rec.merges, vec![(Prefix::new(1, 0), Prefix::new(0, 0), 1 << 61)]);
+
+    // Relocations and rejections are reported for malicious nodes only, and `on_step` once per step
+    let args = ToolArgsBuilder::new().max_join_rate(5.0).build();
+    let mut net = Network::new(2, make_rng(0));
+    let mut rec = Recorder::default();
+    net.add_avail(20, 40);
+    for _ in 0..20 {
+        net.do_step::<RestrictOnePerAge>(&args, &mut rec);
+    }
+    assert_eq!(rec.steps, 20);
+    assert!(!rec.relocations.is_empty());
+    for &(old_prefix, old_name, data) in &rec.relocations {
+        assert!(old_prefix.matches(old_name));
+        assert!(data.is_malicious() && data.age() > 0);
+    }
+    assert!(!rec.rejections.is_empty());
+    assert!(rec.rejections.iter().all(|data| data.is_malicious() && data.age() <= 1));
+}
@@ -27,9 +27,8 @@ use std::cmp::Ordering;
 use std::mem;
 use std::hash::{Hash, Hasher};
 use std::fmt::{self, Formatter, Binary, Debug};
-use std::u64;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use NN;
 
@@ -40,11 +39,6 @@ trait NameT: Ord {
     /// the when `other = 11110000` and `self = 11111111` this is 4.
     fn common_prefix(&self, other: Self) -> usize;
 
-    /// Compares the distance of the arguments to `self`. Returns `Less` if `lhs` is closer,
-    /// `Greater` if `rhs` is closer, and `Equal` if `lhs == rhs`. (The XOR distance can only be
-    /// equal if the arguments ar equal.)
-    fn cmp_distance(&self, lhs: Self, rhs: Self) -> Ordering;
-
     /// Returns `true` if the `i`-th bit is `1`.
     fn bit(&self, i: usize) -> bool;
 
@@ -66,10 +60,6 @@ impl NameT for NN {
         (self ^ other).leading_zeros() as usize
     }
 
-    fn cmp_distance(&self, lhs: Self, rhs: Self) -> Ordering {
-        Ord::cmp(&(lhs ^ self), &(rhs ^ self))
-    }
-
     fn bit(&self, i: usize) -> bool {
         let pow_i = 1 << (mem::size_of::<Self>() * 8 - 1 - i); // 1 on bit i.
         self & pow_i != 0
@@ -106,7 +96,7 @@ impl NameT for NN {
 
 // A group prefix, i.e. a sequence of bits specifying the part of the network's name space
 // consisting of all names that start with this sequence.
-#[derive(Clone, Copy, Default, Eq, Ord)]
+#[derive(Clone, Copy, Default, Eq)]
 pub struct Prefix {
     bit_count: usize,
     name: NN,
@@ -117,7 +107,7 @@ impl Prefix {
     /// Insignificant bits are all set to 0.
     pub fn new(bit_count: usize, name: NN) -> Prefix {
         Prefix {
-            bit_count: bit_count,
+            bit_count,
             name: name.set_remaining(bit_count, false),
         }
     }
@@ -202,16 +192,21 @@ impl Debug for Prefix {
 pub type NodeName = u64;
 
 /// Generate a new node name
-pub fn new_node_name() -> NodeName {
-    thread_rng().gen()
+pub fn new_node_name<R: Rng>(rng: &mut R) -> NodeName {
+    rng.gen()
 }
 
+/// Tag which attack strategies may attach to malicious nodes, e.g. to divide them into groups
+/// with different behaviour. New nodes have cohort 0.
+pub type Cohort = u8;
+
 /// Data stored for a node
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct NodeData {
     age: u32, // initial age is 0
     churns: u32, // initial churns is 0
     is_malicious: bool,
+    cohort: Cohort,
 }
 
 impl NodeData {
@@ -221,6 +216,7 @@ impl NodeData {
             age: 0,
             churns: 0,
             is_malicious: malicious,
+            cohort: 0,
         }
     }
 
@@ -249,7 +245,14 @@ impl NodeData {
     pub fn is_malicious(&self) -> bool {
         self.is_malicious
     }
-}
 
-/// Type of a node
-pub type Node = (NodeName, NodeData);
+    /// Get the cohort (see `Cohort`)
+    pub fn cohort(&self) -> Cohort {
+        self.cohort
+    }
+
+    /// Set the cohort
+    pub fn set_cohort(&mut self, cohort: Cohort) {
+        self.cohort = cohort;
+    }
+}
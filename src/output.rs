@@ -0,0 +1,380 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Output of results
+//!
+//! Each result is turned into a `Row` of named values, which is then written in one of several
+//! formats.
+
+use std::cmp::max;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use rustc_serialize::json::Json;
+
+use {NN, ToolArgs};
+use params::SimParams;
+use tools::{BindingCounts, SimResult, StepRecord, Trials};
+
+
+/// Output format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Fixed-width columns, for reading by humans
+    Table,
+    /// Comma-separated values, with a header line
+    Csv,
+    /// One JSON object per line ("JSON lines")
+    Json,
+}
+
+/// A row of output: a list of named values.
+///
+/// All rows written by one `Writer` must have the same names in the same order. Values which are
+/// lists are omitted from table output.
+pub type Row = Vec<(&'static str, Json)>;
+
+/// Make a row describing a complete result: the parameters as specified, the arguments derived
+/// from them, and the result. Where the result was estimated from repeated simulations, counts,
+/// standard errors and 95% confidence intervals are included.
+///
+/// `seed` is the master seed used for the whole run.
+pub fn make_row(seed: NN, params: &SimParams, args: &ToolArgs, result: &SimResult) -> Row {
+    let mut row = make_params_row(seed, params, args);
+    row.extend(make_result_row(result));
+    row
+}
+
+/// Make the first part of a row from `make_row`, identifying the parameter set.
+pub fn make_params_row(seed: NN, params: &SimParams, args: &ToolArgs) -> Row {
+    let reps = &params.repetitions;
+    vec![("scenario", opt_json(params.scenario.clone().map(Json::String))),
+         ("tool", Json::String(params.sim_type.name().to_string())),
+         ("quorum_alg", Json::String(params.quorum_alg.name().to_string())),
+         ("strategy", Json::String(params.targetting.name().to_string())),
+         ("attack_age", Json::U64(params.attack_age as NN)),
+         ("ager_prop", Json::F64(params.ager_prop)),
+         ("flood_power", Json::F64(params.flood_power)),
+         ("reset_cost", Json::F64(params.reset_cost)),
+         ("budget", opt_json(params.budget.map(Json::F64))),
+         ("nodes", Json::U64(params.num_initial)),
+         ("attacking", Json::String(params.num_attacking.to_string())),
+         ("arrival", Json::String(params.arrival.to_string())),
+         ("max_join", Json::String(params.max_join.to_string())),
+         ("back_join", Json::String(params.add_good.to_string())),
+         ("leave_good", Json::String(params.leave_good.to_string())),
+         ("back_join_schedule", Json::String(params.add_schedule.to_string())),
+         ("leave_schedule", Json::String(params.leave_schedule.to_string())),
+         ("min_group", Json::U64(params.min_group_size)),
+         ("quorum_prop", Json::F64(params.quorum_prop)),
+         ("age_quorum_prop", opt_json(params.age_quorum_prop.map(Json::F64))),
+         ("elders", Json::U64(params.num_elders)),
+         ("age_cap", Json::U64(params.age_cap as NN)),
+         ("proof_time", Json::F64(params.proof_time)),
+         ("max_days", Json::F64(params.max_days)),
+         ("repetitions", Json::U64(reps.batch as NN)),
+         ("precision", opt_json(reps.precision.map(Json::F64))),
+         ("max_trials", opt_json(reps.precision.map(|_| Json::U64(reps.max_trials)))),
+         ("max_time", opt_json(reps.max_time.map(Json::F64))),
+         ("seed", Json::U64(seed)),
+         // Values derived in ToolArgs which are not already given above:
+         ("n_attacking", Json::U64(args.num_attacking)),
+         ("join_per_step", Json::F64(args.max_join_rate)),
+         ("back_join_per_step", Json::F64(args.add_rate_good)),
+         ("p_leave_per_step", Json::F64(args.leave_rate_good)),
+         ("max_steps", Json::U64(args.max_steps)),
+         ("sim_seed", Json::U64(args.seed))]
+}
+
+// Make the last part of a row from `make_row`, describing the result.
+fn make_result_row(result: &SimResult) -> Row {
+    let binding = |counts: fn(&Trials) -> BindingCounts, part: fn(BindingCounts) -> NN| {
+        opt_json(result.trials().map(|t| Json::U64(part(counts(t)))))
+    };
+    vec![("trials", opt_json(result.trials().map(|t| Json::U64(t.n)))),
+         ("n_disrupt", opt_json(result.trials().map(|t| Json::U64(t.disrupted())))),
+         ("p_disrupt", Json::F64(result.p_disrupt())),
+         ("se_disrupt", opt_json(result.se_disrupt().map(Json::F64))),
+         ("ci_disrupt_lo", opt_json(result.ci_disrupt().map(|ci| Json::F64(ci.0)))),
+         ("ci_disrupt_hi", opt_json(result.ci_disrupt().map(|ci| Json::F64(ci.1)))),
+         ("n_compromise", opt_json(result.trials().map(|t| Json::U64(t.compromised())))),
+         ("p_compromise", Json::F64(result.p_compromise())),
+         ("se_compromise", opt_json(result.se_compromise().map(Json::F64))),
+         ("ci_compromise_lo", opt_json(result.ci_compromise().map(|ci| Json::F64(ci.0)))),
+         ("ci_compromise_hi", opt_json(result.ci_compromise().map(|ci| Json::F64(ci.1)))),
+         ("mean_days_disrupt", opt_json(result.mean_days_to_disrupt().map(Json::F64))),
+         ("median_days_disrupt", opt_json(result.median_days_to_disrupt().map(Json::F64))),
+         ("mean_days_compromise", opt_json(result.mean_days_to_compromise().map(Json::F64))),
+         ("median_days_compromise",
+          opt_json(result.median_days_to_compromise().map(Json::F64))),
+         ("mean_spent_compromise",
+          opt_json(result.mean_spent_to_compromise().map(Json::F64))),
+         ("disrupt_by_count", binding(Trials::disrupt_binding, |b| b.count)),
+         ("disrupt_by_age", binding(Trials::disrupt_binding, |b| b.age)),
+         ("disrupt_by_both", binding(Trials::disrupt_binding, |b| b.both)),
+         ("compromise_by_count", binding(Trials::compromise_binding, |b| b.count)),
+         ("compromise_by_age", binding(Trials::compromise_binding, |b| b.age)),
+         ("compromise_by_both", binding(Trials::compromise_binding, |b| b.both)),
+         ("blocked_by_count", binding(Trials::blocked_binding, |b| b.count)),
+         ("blocked_by_age", binding(Trials::blocked_binding, |b| b.age)),
+         ("blocked_by_both", binding(Trials::blocked_binding, |b| b.both)),
+         ("compromise_days",
+          opt_json(result.trials()
+              .map(|t| Json::Array(t.compromise_days().iter().map(|d| Json::F64(*d)).collect())))),
+         ("compromise_cdf",
+          opt_json(result.trials().map(|_| {
+              Json::Array(result.compromise_cdf()
+                  .into_iter()
+                  .map(|(day, p)| Json::Array(vec![Json::F64(day), Json::F64(p)]))
+                  .collect())
+          })))]
+}
+
+/// Make a row describing one step of a traced simulation.
+pub fn make_trace_row(record: &StepRecord) -> Row {
+    let stats = &record.stats;
+    vec![("step", Json::U64(record.step)),
+         ("phase", Json::String(record.phase.name().to_string())),
+         ("day", opt_json(record.day.map(Json::F64))),
+         ("groups", Json::U64(stats.groups as NN)),
+         ("min_group_size", Json::U64(stats.min_group_size as NN)),
+         ("mean_group_size", Json::F64(stats.mean_group_size)),
+         ("max_group_size", Json::U64(stats.max_group_size as NN)),
+         ("good", Json::U64(stats.good as NN)),
+         ("malicious", Json::U64(stats.malicious as NN)),
+         ("worst_malicious_fraction", Json::F64(stats.worst_malicious_fraction)),
+         ("avail_good", Json::U64(stats.avail_good)),
+         ("avail_malicious", Json::U64(stats.avail_malicious)),
+         ("pending", Json::U64(stats.pending as NN)),
+         ("attack_spent", Json::F64(stats.attack_spent))]
+}
+
+// Values not available are written as null / empty.
+fn opt_json(value: Option<Json>) -> Json {
+    value.unwrap_or(Json::Null)
+}
+
+// Format a value for CSV or table output.
+fn cell(value: &Json) -> String {
+    match *value {
+        Json::String(ref s) => s.clone(),
+        Json::F64(x) => x.to_string(),
+        Json::Null => String::new(),
+        ref v => v.to_string(),
+    }
+}
+
+// Quote a CSV cell if necessary.
+fn csv_quote(s: String) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s
+    }
+}
+
+// Split a line of CSV into (unquoted) cells.
+fn csv_split(line: &str) -> Vec<String> {
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                let _ = chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+/// Parameter sets with results in previously written output, used to resume an interrupted run.
+///
+/// Only CSV and JSON output can be read.
+pub struct Completed {
+    // Rows (as cells by column name), indexed by "sim_seed"
+    rows: HashMap<String, Vec<HashMap<String, String>>>,
+}
+
+impl Completed {
+    /// Read output written in `format`. Any incomplete last line should be removed first.
+    pub fn read(format: Format, text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().filter(|l| !l.1.is_empty());
+        let mut rows = HashMap::new();
+        let mut add_row = |row: HashMap<String, String>| -> Result<(), String> {
+            let seed = row.get("sim_seed").cloned().ok_or("no sim_seed column")?;
+            rows.entry(seed).or_insert_with(Vec::new).push(row);
+            Ok(())
+        };
+        match format {
+            Format::Table => return Err("cannot read table output; use CSV or JSON".to_string()),
+            Format::Csv => {
+                let header = match lines.next() {
+                    Some(line) => csv_split(line.1),
+                    None => vec![],
+                };
+                for (i, line) in lines {
+                    let cells = csv_split(line);
+                    if cells.len() != header.len() {
+                        return Err(format!("line {}: expected {} cells", i + 1, header.len()));
+                    }
+                    add_row(header.iter().cloned().zip(cells).collect())?;
+                }
+            }
+            Format::Json => {
+                for (i, line) in lines {
+                    let object = match Json::from_str(line) {
+                        Ok(Json::Object(object)) => object,
+                        _ => return Err(format!("line {}: expected a JSON object", i + 1)),
+                    };
+                    add_row(object.iter().map(|(k, v)| (k.clone(), cell(v))).collect())?;
+                }
+            }
+        }
+        Ok(Completed { rows })
+    }
+
+    /// The master seed used, if all results read have the same one
+    pub fn seed(&self) -> Option<NN> {
+        let mut seeds = self.rows.values().flat_map(|v| v.iter()).map(|row| row.get("seed"));
+        let first = seeds.next()??;
+        if seeds.all(|seed| seed == Some(first)) {
+            first.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Whether a result is present for the parameter set described by `make_params_row`.
+    pub fn contains(&self, params_row: &Row) -> bool {
+        let seed = params_row.iter().find(|c| c.0 == "sim_seed").map(|c| cell(&c.1));
+        let candidates = match seed.and_then(|seed| self.rows.get(&seed)) {
+            Some(rows) => rows,
+            None => return false,
+        };
+        candidates.iter().any(|row| {
+            params_row.iter().all(|c| row.get(c.0).is_some_and(|v| same_cell(v, &cell(&c.1))))
+        })
+    }
+}
+
+// Compare cells, allowing for rounding of numbers when read back.
+fn same_cell(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => (x - y).abs() <= 1e-12 * x.abs().max(y.abs()),
+        _ => false,
+    }
+}
+
+/// Writes rows in some format. A header is written before the first row where the format
+/// requires one.
+pub struct Writer {
+    format: Format,
+    out: Box<dyn Write>,
+    header_written: bool,
+    col_widths: Vec<usize>,
+}
+
+impl Writer {
+    /// Create, writing to `out`
+    pub fn new(format: Format, out: Box<dyn Write>) -> Self {
+        Writer {
+            format,
+            out,
+            header_written: false,
+            col_widths: vec![],
+        }
+    }
+
+    /// Do not write a header (e.g. when appending to previous output)
+    pub fn skip_header(&mut self) {
+        self.header_written = true;
+    }
+
+    /// Write a row
+    pub fn write_row(&mut self, row: &Row) -> io::Result<()> {
+        match self.format {
+            Format::Table => {
+                let row: Vec<_> = row.iter().filter(|c| !c.1.is_array()).collect();
+                if !self.header_written {
+                    self.col_widths = row.iter().map(|c| max(c.0.len(), 8)).collect();
+                    let titles: Vec<String> = row.iter()
+                        .zip(self.col_widths.iter())
+                        .map(|(c, w)| format!("{1:<0$}", w, c.0))
+                        .collect();
+                    writeln!(self.out, "{}", titles.join(" "))?;
+                    self.header_written = true;
+                }
+                let cells: Vec<String> = row.iter()
+                    .zip(self.col_widths.iter())
+                    .map(|(c, w)| format!("{1:<0$}", w, cell(&c.1)))
+                    .collect();
+                writeln!(self.out, "{}", cells.join(" "))
+            }
+            Format::Csv => {
+                if !self.header_written {
+                    let titles: Vec<&str> = row.iter().map(|c| c.0).collect();
+                    writeln!(self.out, "{}", titles.join(","))?;
+                    self.header_written = true;
+                }
+                let cells: Vec<String> = row.iter().map(|c| csv_quote(cell(&c.1))).collect();
+                writeln!(self.out, "{}", cells.join(","))
+            }
+            Format::Json => {
+                // Json::Object would sort the keys, so we write the object ourselves to keep the
+                // column order.
+                let fields: Vec<String> = row.iter()
+                    .map(|c| format!("{}:{}", Json::String(c.0.to_string()), c.1))
+                    .collect();
+                writeln!(self.out, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    /// Flush output
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[test]
+fn test_completed() {
+    assert_eq!(csv_split(r#"a,"b,c",,"d""e""#), vec!["a", "b,c", "", "d\"e"]);
+    let row: Row = vec![("name", Json::String("x, y".to_string())),
+                        ("p", Json::F64(0.1)),
+                        ("seed", Json::U64(3)),
+                        ("sim_seed", Json::U64(5))];
+    let csv = "name,p,seed,sim_seed,result\n\"x, y\",0.1,3,5,1\n";
+    let json = r#"{"name":"x, y","p":0.1,"seed":3,"sim_seed":5,"result":1}"#;
+    for &(format, text) in &[(Format::Csv, csv), (Format::Json, json)] {
+        let completed = Completed::read(format, text).expect("read");
+        assert!(completed.contains(&row));
+        assert_eq!(completed.seed(), Some(3));
+        let mut other = row.clone();
+        other[1].1 = Json::F64(0.2);
+        assert!(!completed.contains(&other));
+    }
+}
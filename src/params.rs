@@ -0,0 +1,914 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Simulation parameters
+//!
+//! `SimParams` describes one simulation in the units used on the command line, and can be
+//! converted to `ToolArgs` and run. Ranges of values for parameter sweeps are described with
+//! `SamplePoints`.
+
+use std::error::Error;
+use std::str::FromStr;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::AddAssign;
+use std::sync::atomic::AtomicUsize;
+use std::cmp::Ordering;
+
+use {ToolArgs, ArgsError, NN, RR};
+use arrival::Arrival;
+use schedule::Schedule;
+use tools::{Tool, DirectCalcTool, SimStructureTool, FullSimTool, SimResult, Repetitions,
+            StepObserver};
+use quorum::{Quorum, SimpleQuorum, AgeQuorum, ElderQuorum, WeightedAgeQuorum, AgeWeight,
+             BftQuorum};
+use attack::{AttackStrategy, UntargettedAttack, SimpleTargettedAttack, AgeTargettedAttack,
+             TwoCohortAttack, MergeForcingAttack, FloodAttack};
+
+
+pub trait DefaultStep<T> {
+    // Return a default step.
+    //
+    // The value `x` is passed so that RelOrAbs can see whether it's being
+    // used in relative or absolute form.
+    fn default_step(x: T) -> T;
+}
+
+impl DefaultStep<NN> for NN {
+    fn default_step(_: NN) -> NN {
+        1
+    }
+}
+
+impl DefaultStep<RR> for RR {
+    fn default_step(_: RR) -> RR {
+        1.0
+    }
+}
+
+#[derive(Clone)]
+pub enum SamplePoints<T> {
+    Range(T, T, Option<T>), // start, stop, optional step
+    List(Vec<T>),
+    Number(T),
+}
+
+impl<T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T>> SamplePoints<T> {
+    pub fn iter(&self) -> SamplePointsIterator<'_, T> {
+        SamplePointsIterator {
+            iterable: self,
+            i: 0,
+            prev: None,
+        }
+    }
+}
+
+/// Error parsing a parameter value
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    value: String,
+    expected: &'static str,
+}
+
+impl ParseError {
+    fn new(value: &str, expected: &'static str) -> Self {
+        ParseError {
+            value: value.to_string(),
+            expected,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "invalid value '{}': expected {}", self.value, self.expected)
+    }
+}
+
+impl Error for ParseError {}
+
+impl<T: FromStr + Copy + AddAssign + PartialOrd<T>> FromStr for SamplePoints<T> {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |x: &str| x.parse::<T>().map_err(|_| ParseError::new(s, SAMPLE_POINTS_SYNTAX));
+        if s.contains('-') {
+            // We have a range; check for a step:
+            let (first, step) = if s.contains(':') {
+                let mut parts = s.split(':');
+                let first = parts.next().expect("split half");
+                let second = parts.next().expect("split half");
+                if parts.next().is_some() {
+                    return Err(ParseError::new(s, "'start-stop:step'"));
+                }
+                (first, Some(parse(second)?))
+            } else {
+                (s, None)
+            };
+            let parts: Vec<&str> = first.split('-').collect();
+            if parts.len() != 2 {
+                return Err(ParseError::new(s, "'start-stop:step'"));
+            }
+            let (start, stop) = (parse(parts[0])?, parse(parts[1])?);
+            // Values which can't be compared mix percentages and absolute values (or are NaN)
+            if start.partial_cmp(&stop).is_none() ||
+               step.is_some_and(|step| start.partial_cmp(&step).is_none()) {
+                return Err(ParseError::new(s, "a range of all percentages or all absolute values"));
+            }
+            if let Some(step) = step {
+                let mut next = start;
+                next += step;
+                if next <= start {
+                    return Err(ParseError::new(s, "a range with a positive step"));
+                }
+            }
+            Ok(SamplePoints::Range(start, stop, step))
+        } else if s.contains(',') {
+            // We have a list
+            let list: Result<Vec<T>, ParseError> = s.split(',').map(parse).collect();
+            Ok(SamplePoints::List(list?))
+        } else {
+            // Presumably we have a single number
+            Ok(SamplePoints::Number(parse(s)?))
+        }
+    }
+}
+
+const SAMPLE_POINTS_SYNTAX: &str = "a number (5), list (1,2,3), range (10-20) or range with step \
+                                    (10-20:5), possibly as percentages where allowed";
+
+pub struct SamplePointsIterator<'a, T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T> + 'a> {
+    iterable: &'a SamplePoints<T>,
+    i: usize,
+    prev: Option<T>,
+}
+
+impl<'a, T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T> + 'a> Iterator
+        for SamplePointsIterator<'a, T>
+{
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.i;
+        match self.iterable {
+            &SamplePoints::Range(start, stop, step) => {
+                match self.prev {
+                    None => {
+                        self.prev = Some(start);
+                        self.prev
+                    },
+                    Some(mut x) => {
+                        let step = step.unwrap_or(T::default_step(start));
+                        x += step;
+                        self.prev = Some(x);
+                        if x > stop {
+                            None
+                        } else {
+                            Some(x)
+                        }
+                    },
+                }
+            },
+            SamplePoints::List(v) => {
+                if i >= v.len() {
+                    None
+                } else {
+                    self.i = i + 1;
+                    Some(v[i])
+                }
+            },
+            &SamplePoints::Number(n) => {
+                if i > 0 {
+                    None
+                } else {
+                    self.i = 1;
+                    Some(n)
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum SimType {
+    DirectCalc,
+    Structure,
+    FullSim,
+}
+
+impl SimType {
+    /// Get from name as used on the command line
+    pub fn from_name(name: &str) -> Option<SimType> {
+        match name {
+            "calc" => Some(SimType::DirectCalc),
+            "structure" => Some(SimType::Structure),
+            "full" => Some(SimType::FullSim),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SimType::DirectCalc => "dir_calc",
+            SimType::Structure => "structure",
+            SimType::FullSim => "full_sim",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+
+pub enum AttackType {
+    Untargetted,
+    SimpleTargetted,
+    AgeTargetted,
+    TwoCohort,
+    MergeForcing,
+    JoinFlood,
+}
+
+impl AttackType {
+    /// Get the list of strategies selected by a name as used on the command line ("all"
+    /// selects all strategies).
+    pub fn from_name(name: &str) -> Option<Vec<AttackType>> {
+        match name {
+            "none" => Some(vec![AttackType::Untargetted]),
+            "simple" => Some(vec![AttackType::SimpleTargetted]),
+            "age" => Some(vec![AttackType::AgeTargetted]),
+            "cohort" => Some(vec![AttackType::TwoCohort]),
+            "merge" => Some(vec![AttackType::MergeForcing]),
+            "flood" => Some(vec![AttackType::JoinFlood]),
+            "all" => {
+                Some(vec![AttackType::Untargetted,
+                          AttackType::SimpleTargetted,
+                          AttackType::AgeTargetted,
+                          AttackType::TwoCohort,
+                          AttackType::MergeForcing,
+                          AttackType::JoinFlood])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AttackType::Untargetted => "untarg.",
+            AttackType::SimpleTargetted => "simp_targ",
+            AttackType::AgeTargetted => "age_targ",
+            AttackType::TwoCohort => "two_cohort",
+            AttackType::MergeForcing => "merge_force",
+            AttackType::JoinFlood => "join_flood",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RelOrAbs<T> {
+    Rel(RR),
+    Abs(T),
+}
+
+impl RelOrAbs<NN> {
+    pub fn resolve(self, base: RR) -> NN {
+        match self {
+            RelOrAbs::Rel(r) => (base * r).round() as NN,
+            RelOrAbs::Abs(n) => n,
+        }
+    }
+}
+
+impl RelOrAbs<RR> {
+    pub fn resolve(self, base: RR) -> RR {
+        match self {
+            RelOrAbs::Rel(r) => base * r,
+            RelOrAbs::Abs(n) => n,
+        }
+    }
+}
+
+impl<T: Display> Display for RelOrAbs<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            // round to avoid printing 7% as 7.000000000000001%
+            RelOrAbs::Rel(r) => write!(f, "{}%", (r * 1e11).round() / 1e9),
+            RelOrAbs::Abs(ref n) => n.fmt(f),
+        }
+    }
+}
+
+impl<T: FromStr> FromStr for RelOrAbs<T> {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(perc) = s.strip_suffix('%') {
+            let perc = perc.parse::<RR>()
+                .map_err(|_| ParseError::new(s, "a number or percentage"))?;
+            Ok(RelOrAbs::Rel(perc * 0.01))
+        } else {
+            s.parse()
+                .map(RelOrAbs::Abs)
+                .map_err(|_| ParseError::new(s, "a number or percentage"))
+        }
+    }
+}
+
+impl<T: AddAssign + Copy> AddAssign for RelOrAbs<T> {
+    fn add_assign(&mut self, rhs: RelOrAbs<T>) {
+        match (self, rhs) {
+            (&mut RelOrAbs::Rel(ref mut x), RelOrAbs::Rel(y)) => *x += y,
+            (&mut RelOrAbs::Abs(ref mut x), RelOrAbs::Abs(y)) => *x += y,
+            // parsing rejects ranges mixing the two
+            _ => panic!("cannot add relative and absolute values"),
+        }
+    }
+}
+
+impl<T: PartialOrd<T> + Copy> PartialOrd<RelOrAbs<T>> for RelOrAbs<T> {
+    fn partial_cmp(&self, rhs: &RelOrAbs<T>) -> Option<Ordering> {
+        match (self, rhs) {
+            (&RelOrAbs::Rel(x), RelOrAbs::Rel(y)) => x.partial_cmp(y),
+            (&RelOrAbs::Abs(x), RelOrAbs::Abs(y)) => x.partial_cmp(y),
+            // relative and absolute values are not comparable
+            _ => None,
+        }
+    }
+}
+
+impl<T: From<u32>> DefaultStep<RelOrAbs<T>> for RelOrAbs<T> {
+    fn default_step(x: RelOrAbs<T>) -> RelOrAbs<T> {
+        match x {
+            RelOrAbs::Rel(_) => RelOrAbs::Rel(0.1),
+            RelOrAbs::Abs(_) => RelOrAbs::Abs(1.into()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SimParams {
+    pub sim_type: SimType,
+    pub quorum_alg: QuorumAlg,
+    /// Number of elders (oldest nodes of each group) which vote, with `QuorumAlg::Elder`
+    pub num_elders: NN,
+    /// Maximum age counted, with `QuorumAlg::WeightedCapped`
+    pub age_cap: u32,
+    pub targetting: AttackType,
+    /// Age from which nodes target a group, with `AttackType::AgeTargetted`
+    pub attack_age: u32,
+    /// Proportion of malicious nodes kept to age, with `AttackType::TwoCohort`
+    pub ager_prop: RR,
+    /// Work the attacker spends creating identities (node-days per day), with
+    /// `AttackType::JoinFlood`
+    pub flood_power: RR,
+    /// Cost of each new identity or reset as a proportion of proof time, with
+    /// `AttackType::JoinFlood`
+    pub reset_cost: RR,
+    /// Total proof-of-work the attacker can do (node-days); unlimited if `None`
+    pub budget: Option<RR>,
+    pub num_initial: NN,
+    pub num_attacking: RelOrAbs<NN>,
+    /// How malicious nodes arrive during the attack
+    pub arrival: Arrival,
+    pub max_join: RelOrAbs<RR>,
+    pub add_good: RelOrAbs<RR>,
+    pub leave_good: RelOrAbs<RR>,
+    /// Factors applied to `add_good` and `leave_good` over the days of the attack
+    pub add_schedule: Schedule,
+    pub leave_schedule: Schedule,
+    pub min_group_size: NN,
+    pub quorum_prop: RR,
+    /// Proportion of group age required with an age quorum; the same as `quorum_prop` if `None`
+    pub age_quorum_prop: Option<RR>,
+    pub proof_time: RR,
+    pub max_days: RR,
+    pub repetitions: Repetitions,
+    /// Name of the scenario (from an experiment file) this belongs to, if any
+    pub scenario: Option<String>,
+}
+
+impl Display for SimParams {
+    // Names are those of output columns.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(ref name) = self.scenario {
+            write!(f, "scenario={} ", name)?;
+        }
+        write!(f,
+               "tool={} quorum_alg={} strategy={} nodes={} attacking={} arrival={} max_join={} \
+                back_join={} leave_good={} min_group={} quorum_prop={} proof_time={} max_days={}",
+               self.sim_type.name(),
+               self.quorum_alg.name(),
+               self.targetting.name(),
+               self.num_initial,
+               self.num_attacking,
+               self.arrival,
+               self.max_join,
+               self.add_good,
+               self.leave_good,
+               self.min_group_size,
+               self.quorum_prop,
+               self.proof_time,
+               self.max_days)?;
+        if let Some(q) = self.age_quorum_prop {
+            write!(f, " age_quorum_prop={}", q)?;
+        }
+        if self.quorum_alg == QuorumAlg::Elder {
+            write!(f, " elders={}", self.num_elders)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quorum algorithm used by the full simulation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuorumAlg {
+    Simple,
+    Age,
+    Elder,
+    WeightedLinear,
+    WeightedExp,
+    WeightedCapped,
+    WeightedSqrt,
+    Bft,
+}
+
+impl QuorumAlg {
+    /// Get the list of quorum algorithms selected by a name as used on the command line ("all"
+    /// selects all algorithms).
+    pub fn from_name(name: &str) -> Option<Vec<QuorumAlg>> {
+        match name {
+            "simple" => Some(vec![QuorumAlg::Simple]),
+            "age" => Some(vec![QuorumAlg::Age]),
+            "elder" => Some(vec![QuorumAlg::Elder]),
+            "age_linear" => Some(vec![QuorumAlg::WeightedLinear]),
+            "age_exp" => Some(vec![QuorumAlg::WeightedExp]),
+            "age_capped" => Some(vec![QuorumAlg::WeightedCapped]),
+            "age_sqrt" => Some(vec![QuorumAlg::WeightedSqrt]),
+            "bft" => Some(vec![QuorumAlg::Bft]),
+            "weighted" => {
+                Some(vec![QuorumAlg::WeightedLinear,
+                          QuorumAlg::WeightedExp,
+                          QuorumAlg::WeightedCapped,
+                          QuorumAlg::WeightedSqrt])
+            }
+            "all" => {
+                Some(vec![QuorumAlg::Simple,
+                          QuorumAlg::Age,
+                          QuorumAlg::Elder,
+                          QuorumAlg::WeightedLinear,
+                          QuorumAlg::WeightedExp,
+                          QuorumAlg::WeightedCapped,
+                          QuorumAlg::WeightedSqrt,
+                          QuorumAlg::Bft])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            QuorumAlg::Simple => "simple",
+            QuorumAlg::Age => "age",
+            QuorumAlg::Elder => "elder",
+            QuorumAlg::WeightedLinear => "age_linear",
+            QuorumAlg::WeightedExp => "age_exp",
+            QuorumAlg::WeightedCapped => "age_capped",
+            QuorumAlg::WeightedSqrt => "age_sqrt",
+            QuorumAlg::Bft => "bft",
+        }
+    }
+
+    /// Whether the algorithm uses a proportion of group age (`SimParams::age_quorum_prop`)
+    pub fn uses_age(&self) -> bool {
+        !matches!(*self, QuorumAlg::Simple | QuorumAlg::Elder | QuorumAlg::Bft)
+    }
+}
+
+/// Ranges of parameters to simulate. Every combination of values is simulated.
+#[derive(Clone)]
+pub struct SweepSpec {
+    pub sim_type: SimType,
+    pub nodes: SamplePoints<NN>,
+    pub attacking: SamplePoints<RelOrAbs<NN>>,
+    pub max_join: SamplePoints<RelOrAbs<RR>>,
+    pub add_good: SamplePoints<RelOrAbs<RR>>,
+    pub leave_good: SamplePoints<RelOrAbs<RR>>,
+    pub group_size: SamplePoints<NN>,
+    pub quorum: SamplePoints<RR>,
+    /// Proportions of group age required with an age quorum; the same as `quorum` if `None`
+    pub age_quorum_prop: Option<SamplePoints<RR>>,
+    pub proof_time: SamplePoints<RR>,
+    pub max_days: SamplePoints<RR>,
+    pub quorum_alg: Vec<QuorumAlg>,
+    /// Numbers of elders, used only with `QuorumAlg::Elder`
+    pub elders: SamplePoints<NN>,
+    pub age_cap: u32,
+    pub targetting: Vec<AttackType>,
+    pub arrival: Vec<Arrival>,
+    pub attack_age: u32,
+    pub ager_prop: RR,
+    pub flood_power: RR,
+    pub reset_cost: RR,
+    pub budget: Option<RR>,
+    pub add_schedule: Schedule,
+    pub leave_schedule: Schedule,
+    pub repetitions: Repetitions,
+    pub scenario: Option<String>,
+}
+
+impl SweepSpec {
+    /// Create with default values (a single parameter set)
+    pub fn new() -> Self {
+        SweepSpec {
+            sim_type: SimType::FullSim,
+            nodes: SamplePoints::Number(1000),
+            attacking: SamplePoints::Number(RelOrAbs::Rel(0.1)),
+            max_join: SamplePoints::Number(RelOrAbs::Rel(0.02)),
+            add_good: SamplePoints::Number(RelOrAbs::Rel(0.001)),
+            leave_good: SamplePoints::Number(RelOrAbs::Rel(0.001)),
+            group_size: SamplePoints::Number(10),
+            quorum: SamplePoints::Number(0.5),
+            age_quorum_prop: None,
+            proof_time: SamplePoints::Number(1.0),
+            max_days: SamplePoints::Number(100.0),
+            quorum_alg: vec![QuorumAlg::Simple],
+            elders: SamplePoints::Number(7),
+            age_cap: 4,
+            targetting: vec![AttackType::Untargetted],
+            arrival: vec![Arrival::Once],
+            attack_age: 2,
+            ager_prop: 0.5,
+            flood_power: 10.0,
+            reset_cost: 1.0,
+            budget: None,
+            add_schedule: Schedule::Constant,
+            leave_schedule: Schedule::Constant,
+            repetitions: Repetitions::fixed(100),
+            scenario: None,
+        }
+    }
+
+    /// Check values which are not checked when converting each parameter set to `ToolArgs`.
+    /// Both the command line and experiment files are checked here.
+    pub fn check(&self) -> Result<(), ArgsError> {
+        if !(0.0..=1.0).contains(&self.ager_prop) {
+            return Err(ArgsError::AgerProp(self.ager_prop));
+        }
+        if self.flood_power.is_nan() || self.flood_power < 0.0 {
+            return Err(ArgsError::FloodPower(self.flood_power));
+        }
+        if self.reset_cost.is_nan() || self.reset_cost <= 0.0 {
+            return Err(ArgsError::ResetCost(self.reset_cost));
+        }
+        if let Some(budget) = self.budget.filter(|&b| b.is_nan() || b < 0.0) {
+            return Err(ArgsError::Budget(budget));
+        }
+        if self.elders.iter().any(|n| n == 0) {
+            return Err(ArgsError::NoElders);
+        }
+        self.repetitions.check()
+    }
+
+    /// Expand to the list of all combinations of parameters (the cartesian product of all
+    /// ranges).
+    pub fn expand(&self) -> Vec<SimParams> {
+        let mut nodes_iter = self.nodes.iter();
+        let mut at_nodes_iter = self.attacking.iter();
+        let mut max_join_iter = self.max_join.iter();
+        let mut add_good_iter = self.add_good.iter();
+        let mut leave_good_iter = self.leave_good.iter();
+        let mut group_size_iter = self.group_size.iter();
+        let mut quorum_iter = self.quorum.iter();
+        let mut proof_time_iter = self.proof_time.iter();
+        let mut max_days_iter = self.max_days.iter();
+        let mut quorum_alg_iter = self.quorum_alg.iter();
+        let mut at_type_iter = self.targetting.iter();
+        let mut arrival_iter = self.arrival.iter();
+
+        let mut v = vec![SimParams {
+                             sim_type: self.sim_type,
+                             num_initial: nodes_iter.next().expect("first iter item"),
+                             num_attacking: at_nodes_iter.next().expect("first iter item"),
+                             max_join: max_join_iter.next().expect("first iter item"),
+                             add_good: add_good_iter.next().expect("first iter item"),
+                             leave_good: leave_good_iter.next().expect("first iter item"),
+                             min_group_size: group_size_iter.next().expect("first iter item"),
+                             quorum_prop: quorum_iter.next().expect("first iter item"),
+                             age_quorum_prop: None,
+                             proof_time: proof_time_iter.next().expect("first iter item"),
+                             max_days: max_days_iter.next().expect("first iter item"),
+                             quorum_alg: *quorum_alg_iter.next().expect("first iter item"),
+                             num_elders: self.elders.iter().next().expect("first iter item"),
+                             age_cap: self.age_cap,
+                             targetting: *at_type_iter.next().expect("first iter item"),
+                             arrival: arrival_iter.next().expect("first iter item").clone(),
+                             attack_age: self.attack_age,
+                             ager_prop: self.ager_prop,
+                             flood_power: self.flood_power,
+                             reset_cost: self.reset_cost,
+                             budget: self.budget,
+                             add_schedule: self.add_schedule.clone(),
+                             leave_schedule: self.leave_schedule.clone(),
+                             repetitions: self.repetitions,
+                             scenario: self.scenario.clone(),
+                         }];
+
+        // TODO: check we're not going to cause out-of-memory here!
+
+        // Replicate for all network sizes (num nodes)
+        let range = 0..v.len();
+        for n in nodes_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.num_initial = n;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all numbers of malicious nodes
+        let range = 0..v.len();
+        for r in at_nodes_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.num_attacking = r;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all join rates of good nodes
+        let range = 0..v.len();
+        for x in max_join_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.max_join = x;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all leave rates of good nodes
+        let range = 0..v.len();
+        for x in add_good_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.add_good = x;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all leave rates of good nodes
+        let range = 0..v.len();
+        for x in leave_good_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.leave_good = x;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all group sizes
+        let range = 0..v.len();
+        for g in group_size_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.min_group_size = g;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all quorum sizes
+        let range = 0..v.len();
+        for q in quorum_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.quorum_prop = q;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all proof-of-work times
+        let range = 0..v.len();
+        for x in proof_time_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.proof_time = x;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all max days
+        let range = 0..v.len();
+        for x in max_days_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.max_days = x;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all quorum types
+        let range = 0..v.len();
+        for q in quorum_alg_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.quorum_alg = *q;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all attack strategies
+        let range = 0..v.len();
+        for at in at_type_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.targetting = *at;
+                v.push(s);
+            }
+        }
+
+        // Replicate for all arrival profiles
+        let range = 0..v.len();
+        for a in arrival_iter {
+            for i in range.clone() {
+                let mut s = v[i].clone();
+                s.arrival = a.clone();
+                v.push(s);
+            }
+        }
+
+        // Replicate for all age quorum proportions, only where an age quorum is used
+        if let Some(ref props) = self.age_quorum_prop {
+            v = replicate_where(v,
+                                props,
+                                |s| s.quorum_alg.uses_age(),
+                                |s, q| s.age_quorum_prop = Some(q));
+        }
+
+        // Replicate for all numbers of elders, only where an elder quorum is used
+        v = replicate_where(v,
+                            &self.elders,
+                            |s| s.quorum_alg == QuorumAlg::Elder,
+                            |s, n| s.num_elders = n);
+
+        v
+    }
+}
+
+// Replace each parameter set for which `applies` returns true with one copy per value of
+// `points`, modified by `set`.
+fn replicate_where<T, P, F>(v: Vec<SimParams>,
+                            points: &SamplePoints<T>,
+                            applies: P,
+                            set: F)
+                            -> Vec<SimParams>
+    where T: Copy + Debug + AddAssign + PartialOrd<T> + DefaultStep<T>,
+          P: Fn(&SimParams) -> bool,
+          F: Fn(&mut SimParams, T)
+{
+    v.into_iter()
+        .flat_map(|s| if applies(&s) {
+            points.iter()
+                .map(|x| {
+                    let mut s = s.clone();
+                    set(&mut s, x);
+                    s
+                })
+                .collect()
+        } else {
+            vec![s]
+        })
+        .collect()
+}
+
+impl Default for SweepSpec {
+    fn default() -> Self {
+        SweepSpec::new()
+    }
+}
+
+impl SimParams {
+    /// Convert to arguments and run the tool, or fail if the parameters are unusable.
+    pub fn result(&self, seed: NN) -> Result<(ToolArgs, SimResult), ArgsError> {
+        let args = ToolArgs::from_params(self, seed)?;
+        let result = self.run(&args, None);
+        Ok((args, result))
+    }
+
+    /// Run the tool with arguments previously converted from these parameters.
+    ///
+    /// If `reps_done` is given, it is incremented after each repetition of a simulation.
+    pub fn run(&self, args: &ToolArgs, reps_done: Option<&AtomicUsize>) -> SimResult {
+        self.make_tool(args, reps_done).calc_p_compromise(&self.repetitions)
+    }
+
+    /// Run a single simulation, passing the network state after each step to `observer`.
+    ///
+    /// The result is `None` if the tool used does not support tracing.
+    pub fn trace(&self,
+                 seed: NN,
+                 observer: StepObserver)
+                 -> Result<(ToolArgs, Option<SimResult>), ArgsError> {
+        let args = ToolArgs::from_params(self, seed)?;
+        let result = self.make_tool(&args, None).trace_sim(observer);
+        Ok((args, result))
+    }
+
+    fn make_tool<'a>(&self,
+                     args: &'a ToolArgs,
+                     reps_done: Option<&'a AtomicUsize>)
+                     -> Box<dyn Tool + 'a> {
+        match self.sim_type {
+            SimType::DirectCalc => Box::new(DirectCalcTool::new(args)),
+            SimType::Structure => Box::new(SimStructureTool::new(args)),
+            SimType::FullSim => {
+                // note: FullSimTool is templated on quorum and attack strategy parameters, so
+                // we need to create the whole thing at once (not create parameters first)
+                match self.targetting {
+                    AttackType::Untargetted => self.full_sim(args, reps_done, UntargettedAttack),
+                    AttackType::SimpleTargetted => {
+                        self.full_sim(args, reps_done, SimpleTargettedAttack::new())
+                    }
+                    AttackType::AgeTargetted => {
+                        self.full_sim(args, reps_done, AgeTargettedAttack::new(self.attack_age))
+                    }
+                    AttackType::TwoCohort => {
+                        self.full_sim(args, reps_done, TwoCohortAttack::new(self.ager_prop))
+                    }
+                    AttackType::MergeForcing => self.full_sim(args, reps_done, MergeForcingAttack),
+                    AttackType::JoinFlood => {
+                        // work available per step over work per identity
+                        let per_step = self.flood_power * args.step_len() /
+                                       (self.reset_cost * self.proof_time);
+                        self.full_sim(args, reps_done, FloodAttack::new(per_step, self.reset_cost))
+                    }
+                }
+            }
+        }
+    }
+
+    fn full_sim<'a, A>(&self,
+                       args: &'a ToolArgs,
+                       reps_done: Option<&'a AtomicUsize>,
+                       attack: A)
+                       -> Box<dyn Tool + 'a>
+        where A: AttackStrategy + Clone + Sync + 'a
+    {
+        match self.quorum_alg {
+            QuorumAlg::Simple => boxed_full_sim(args, reps_done, SimpleQuorum::new(), attack),
+            QuorumAlg::Age => boxed_full_sim(args, reps_done, AgeQuorum::new(), attack),
+            QuorumAlg::Bft => boxed_full_sim(args, reps_done, BftQuorum::new(), attack),
+            QuorumAlg::Elder => {
+                boxed_full_sim(args, reps_done, ElderQuorum::new(self.num_elders), attack)
+            }
+            QuorumAlg::WeightedLinear |
+            QuorumAlg::WeightedExp |
+            QuorumAlg::WeightedCapped |
+            QuorumAlg::WeightedSqrt => {
+                let weight = match self.quorum_alg {
+                    QuorumAlg::WeightedExp => AgeWeight::Exp,
+                    QuorumAlg::WeightedCapped => AgeWeight::Capped(self.age_cap),
+                    QuorumAlg::WeightedSqrt => AgeWeight::Sqrt,
+                    _ => AgeWeight::Linear,
+                };
+                boxed_full_sim(args, reps_done, WeightedAgeQuorum::new(weight), attack)
+            }
+        }
+    }
+}
+
+fn boxed_full_sim<'a, Q, A>(args: &'a ToolArgs,
+                            reps_done: Option<&'a AtomicUsize>,
+                            quorum: Q,
+                            attack: A)
+                            -> Box<dyn Tool + 'a>
+    where Q: Quorum + Sync + 'a,
+          A: AttackStrategy + Clone + Sync + 'a
+{
+    let tool = FullSimTool::new(args, quorum, attack);
+    match reps_done {
+        Some(counter) => Box::new(tool.count_reps(counter)),
+        None => Box::new(tool),
+    }
+}
+
+#[test]
+fn test_parse_sample_points() {
+    let p: SamplePoints<NN> = "10-20:5".parse().expect("range");
+    assert_eq!(p.iter().collect::<Vec<_>>(), vec![10, 15, 20]);
+    let p: SamplePoints<RelOrAbs<NN>> = "5%,10".parse().expect("list");
+    assert_eq!(p.iter().collect::<Vec<_>>(), vec![RelOrAbs::Rel(0.05), RelOrAbs::Abs(10)]);
+    assert!("10-".parse::<SamplePoints<NN>>().is_err());
+    assert!("1-2-3".parse::<SamplePoints<NN>>().is_err());
+    assert!("1,x".parse::<SamplePoints<NN>>().is_err());
+    assert!("x%".parse::<SamplePoints<RelOrAbs<RR>>>().is_err());
+    assert!("5%-10".parse::<SamplePoints<RelOrAbs<NN>>>().is_err());
+    assert!("5-10:1%".parse::<SamplePoints<RelOrAbs<RR>>>().is_err());
+    assert!("1-5:0".parse::<SamplePoints<NN>>().is_err());
+}
@@ -19,9 +19,54 @@
 
 use std::cmp::min;
 
+use rand::{SeedableRng, XorShiftRng};
+
 use {NN, RR};
 
 
+/// Random number generator used by simulations. Every random draw made while simulating goes
+/// through one of these, so that results can be reproduced from a seed.
+pub type SimRng = XorShiftRng;
+
+// One round of the SplitMix64 generator, used to scramble seeds.
+fn split_mix(x: NN) -> NN {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// Derive the seed of sub-stream `stream` from `seed`.
+///
+/// This is used to give each parameter set and each repetition its own seed, such that results
+/// do not depend on the order in which these are run.
+pub fn sub_seed(seed: NN, stream: NN) -> NN {
+    split_mix(seed ^ split_mix(stream))
+}
+
+/// Create a random number generator from a seed.
+pub fn make_rng(seed: NN) -> SimRng {
+    let a = split_mix(seed);
+    let b = split_mix(a);
+    let mut words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    if words == [0; 4] {
+        // XorShift cannot use an all-zero seed
+        words[0] = 1;
+    }
+    SimRng::from_seed(words)
+}
+
+#[test]
+fn test_make_rng() {
+    use rand::Rng;
+    let a: Vec<NN> = make_rng(sub_seed(5, 1)).gen_iter().take(10).collect();
+    let b: Vec<NN> = make_rng(sub_seed(5, 1)).gen_iter().take(10).collect();
+    let c: Vec<NN> = make_rng(sub_seed(5, 2)).gen_iter().take(10).collect();
+    assert_eq!(a, b);
+    assert!(a != c);
+}
+
+
 /// Calculate `n choose k`, i.e. `n! / (k! (n-k)!)`.
 pub fn choose(n: NN, mut k: NN) -> RR {
     assert!(n >= k);
@@ -44,6 +89,53 @@ fn test_choose() {
     assert_eq!(choose(56, 7) as NN, (8 * 11 * 9 * 53 * 13 * 17 * 25));
 }
 
+/// Standard normal quantile for a two-sided 95% confidence interval.
+pub const Z_95: RR = 1.959963984540054;
+
+/// Calculate the Wilson score interval for a proportion, given `k` successes out of `n` trials
+/// and the standard normal quantile `z` (e.g. `Z_95`).
+///
+/// Unlike the normal approximation this behaves well when `k` is 0 or `n` (e.g. 0 successes
+/// out of 100 trials does not give the interval `(0, 0)`). Returns `(lower, upper)`.
+pub fn wilson_interval(k: NN, n: NN, z: RR) -> (RR, RR) {
+    assert!(k <= n, "expected k <= n; found k={}, n={}", k, n);
+    if n == 0 {
+        return (0.0, 1.0);
+    }
+    let n = n as RR;
+    let p = k as RR / n;
+    let z2 = z * z;
+    let denom = 1.0 + z2 / n;
+    let centre = (p + z2 / (2.0 * n)) / denom;
+    let half_width = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denom;
+    // The bounds are exact at the extremes; avoid rounding errors there.
+    let lower = if k == 0 { 0.0 } else { centre - half_width };
+    let upper = if k as RR == n { 1.0 } else { centre + half_width };
+    (lower, upper)
+}
+
+/// Standard error of a proportion estimated from `k` successes out of `n` trials.
+pub fn std_error(k: NN, n: NN) -> RR {
+    if n == 0 {
+        return 0.0;
+    }
+    let p = k as RR / n as RR;
+    (p * (1.0 - p) / n as RR).sqrt()
+}
+
+#[test]
+fn test_wilson_interval() {
+    let (lo, hi) = wilson_interval(0, 100, Z_95);
+    assert_eq!(lo, 0.0);
+    assert!((hi - 0.03699).abs() < 1e-4);
+    let (lo, hi) = wilson_interval(50, 100, Z_95);
+    assert!((lo - 0.40383).abs() < 1e-4);
+    assert!((hi - 0.59617).abs() < 1e-4);
+    let (lo, hi) = wilson_interval(100, 100, Z_95);
+    assert!((lo - 0.96301).abs() < 1e-4);
+    assert_eq!(hi, 1.0);
+}
+
 /// Calculate the probability of less than `q` "black" nodes, where there
 /// are `n` total nodes ("red" + "black"), `r` red, and we choose `k`.
 pub fn prob_disruption(n: NN, r: NN, k: NN, q: NN) -> RR {
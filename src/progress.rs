@@ -0,0 +1,98 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Progress reporting on standard error
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Counters updated by the simulations, and displayed periodically by `Progress::display`.
+pub struct Progress {
+    total: usize,
+    /// Number of parameter sets completed
+    pub sets_done: AtomicUsize,
+    /// Number of repetitions completed (over all parameter sets)
+    pub reps_done: AtomicUsize,
+    finished: AtomicBool,
+    start: Instant,
+}
+
+impl Progress {
+    /// Create, for `total` parameter sets
+    pub fn new(total: usize) -> Self {
+        Progress {
+            total,
+            sets_done: AtomicUsize::new(0),
+            reps_done: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+            start: Instant::now(),
+        }
+    }
+
+    /// Whether progress should be displayed: only when standard error is a terminal.
+    pub fn wanted() -> bool {
+        io::stderr().is_terminal()
+    }
+
+    /// Update the display every half second until `finish` is called, then clear it.
+    pub fn display(&self) {
+        let mut stderr = io::stderr();
+        while !self.finished.load(Ordering::Relaxed) {
+            let _ = write!(stderr, "\r{}\x1b[K", self.status());
+            let _ = stderr.flush();
+            thread::sleep(Duration::from_millis(500));
+        }
+        let _ = write!(stderr, "\r\x1b[K");
+        let _ = stderr.flush();
+    }
+
+    /// Stop `display`
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    // One line describing progress
+    fn status(&self) -> String {
+        let sets = self.sets_done.load(Ordering::Relaxed);
+        let reps = self.reps_done.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { reps as f64 / elapsed } else { 0.0 };
+        // Estimate from parameter sets completed; sets may take very different times, so this
+        // is only a rough guide.
+        let eta = if sets > 0 {
+            format_secs(elapsed * (self.total - sets) as f64 / sets as f64)
+        } else {
+            "?".to_string()
+        };
+        format!("{}/{} parameter sets, {} repetitions ({:.1}/s), elapsed {}, ETA {}",
+                sets,
+                self.total,
+                reps,
+                rate,
+                format_secs(elapsed),
+                eta)
+    }
+}
+
+// Format a time as h:mm:ss
+fn format_secs(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
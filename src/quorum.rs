@@ -17,10 +17,118 @@
 
 //! Quorum
 
-use std::collections::HashMap;
-
 use {NN, RR};
-use node::{NodeName, NodeData};
+use net::Group;
+use node::NodeData;
+#[cfg(test)]
+use node::NodeName;
+
+
+/// Which of a quorum's conditions decided a verdict: the count of nodes, their age, or both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    /// The condition on the number of nodes
+    Count,
+    /// The condition on (weighted) age
+    Age,
+    /// Both conditions equally
+    Both,
+}
+
+impl Binding {
+    // The failing (or unmet) conditions, given at least one
+    fn of(count: bool, age: bool) -> Binding {
+        match (count, age) {
+            (true, true) => Binding::Both,
+            (false, true) => Binding::Age,
+            _ => Binding::Count,
+        }
+    }
+
+    // The condition with the smallest margin
+    fn tightest(count_margin: RR, age_margin: Option<RR>) -> Binding {
+        match age_margin {
+            Some(age) if age < count_margin => Binding::Age,
+            Some(age) if age == count_margin => Binding::Both,
+            _ => Binding::Count,
+        }
+    }
+}
+
+/// Outcome of checking a group for disruption or compromise, with the proportions it was
+/// decided on.
+///
+/// Proportions are of the nodes which vote (e.g. only elders for `ElderQuorum`). Age proportions
+/// are only given by algorithms which use age, and are of the (weighted) age of those nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Verdict {
+    /// True if the group is disrupted (or compromised, according to the check made)
+    pub failed: bool,
+    /// Proportion of nodes which are good
+    pub good_count: RR,
+    /// Proportion of nodes which are malicious
+    pub bad_count: RR,
+    /// Proportion of age held by good nodes
+    pub good_age: Option<RR>,
+    /// Proportion of age held by malicious nodes
+    pub bad_age: Option<RR>,
+    /// The condition deciding the verdict. For disruption, this is the failing condition if
+    /// disrupted, otherwise the one closest to failing. For compromise, this is the condition
+    /// last met (with the smallest margin) if compromised, otherwise the one not met.
+    pub binding: Binding,
+}
+
+impl Verdict {
+    // Disruption verdict: good nodes must meet the count and age proportions
+    fn disruption(counts: (RR, RR), ages: Option<(RR, RR)>, prop: RR, age_prop: RR) -> Self {
+        let count_fails = counts.0 < prop;
+        let age_fails = ages.is_some_and(|ages| ages.0 < age_prop);
+        let failed = count_fails || age_fails;
+        let binding = if failed {
+            Binding::of(count_fails, age_fails)
+        } else {
+            Binding::tightest(counts.0 - prop, ages.map(|ages| ages.0 - age_prop))
+        };
+        Verdict::new(failed, counts, ages, binding)
+    }
+
+    // Compromise verdict: malicious nodes must meet the count and age proportions
+    fn compromise(counts: (RR, RR), ages: Option<(RR, RR)>, prop: RR, age_prop: RR) -> Self {
+        let count_met = counts.1 >= prop;
+        let age_met = ages.is_none_or(|ages| ages.1 >= age_prop);
+        let failed = count_met && age_met;
+        let binding = if failed {
+            Binding::tightest(counts.1 - prop, ages.map(|ages| ages.1 - age_prop))
+        } else {
+            Binding::of(!count_met, !age_met)
+        };
+        Verdict::new(failed, counts, ages, binding)
+    }
+
+    fn new(failed: bool, counts: (RR, RR), ages: Option<(RR, RR)>, binding: Binding) -> Self {
+        Verdict {
+            failed,
+            good_count: counts.0,
+            bad_count: counts.1,
+            good_age: ages.map(|ages| ages.0),
+            bad_age: ages.map(|ages| ages.1),
+            binding,
+        }
+    }
+}
+
+// Proportions of the nodes which are good and malicious
+fn count_props<'a, I: IntoIterator<Item = &'a NodeData>>(nodes: I) -> (RR, RR) {
+    let mut n = 0;
+    let mut n_bad = 0;
+    for data in nodes {
+        n += 1;
+        if data.is_malicious() {
+            n_bad += 1;
+        }
+    }
+    ((n - n_bad) as RR / n as RR, n_bad as RR / n as RR)
+}
 
 
 /// Describes the "quorum" algorithm
@@ -36,11 +144,26 @@ pub trait Quorum {
     /// greater than half if number must be greater than 50%.
     fn set_quorum_proportion(&mut self, prop: RR);
 
-    /// Returns true if there is not a quorum of good nodes in the passed group.
-    fn quorum_disrupted(&self, group: &HashMap<NodeName, NodeData>) -> bool;
+    /// Specify proportion of group age required, range 0-1, where the algorithm uses age.
+    ///
+    /// Default implementation: ignored.
+    fn set_age_proportion(&mut self, _prop: RR) {}
+
+    /// Check whether there is not a quorum of good nodes in the passed group (the verdict has
+    /// `failed` set if so).
+    fn quorum_disrupted(&self, group: &Group) -> Verdict;
 
-    /// Returns true if there is a quorum of bad nodes in the passed group.
-    fn quorum_compromised(&self, group: &HashMap<NodeName, NodeData>) -> bool;
+    /// Check whether there is a quorum of bad nodes in the passed group (the verdict has
+    /// `failed` set if so).
+    fn quorum_compromised(&self, group: &Group) -> Verdict;
+
+    /// Whether a compromised group should also be counted as disrupted. If not, simulations
+    /// continue after compromise until disruption occurs too.
+    ///
+    /// Default implementation: true.
+    fn compromise_implies_disruption(&self) -> bool {
+        true
+    }
 }
 
 /// Quorum based on simply meeting some minimum proportion of the group.
@@ -60,6 +183,12 @@ impl SimpleQuorum {
     }
 }
 
+impl Default for SimpleQuorum {
+    fn default() -> Self {
+        SimpleQuorum::new()
+    }
+}
+
 impl Quorum for SimpleQuorum {
     fn quorum_size(&self, k: NN) -> Option<NN> {
         Some((k as RR * self.proportion).ceil() as NN)
@@ -69,31 +198,57 @@ impl Quorum for SimpleQuorum {
         self.proportion = prop;
     }
 
-    fn quorum_disrupted(&self, group: &HashMap<NodeName, NodeData>) -> bool {
-        let good = group.iter().filter(|node| !node.1.is_malicious()).count() as RR;
-        let all = group.len() as RR;
-        good / all < self.proportion
+    fn quorum_disrupted(&self, group: &Group) -> Verdict {
+        Verdict::disruption(count_props(group.values()), None, self.proportion, 0.0)
     }
 
-    fn quorum_compromised(&self, group: &HashMap<NodeName, NodeData>) -> bool {
-        let bad = group.iter().filter(|node| node.1.is_malicious()).count() as RR;
-        let all = group.len() as RR;
-        bad / all >= self.proportion
+    fn quorum_compromised(&self, group: &Group) -> Verdict {
+        Verdict::compromise(count_props(group.values()), None, self.proportion, 0.0)
     }
 }
 
 /// Quorum which requires some proportion of group age as well as number
 ///
-/// We require the same proportion of age as of the number of nodes (although
-/// these could be separated).
+/// Unless set separately with `set_age_proportion`, we require the same proportion of age as of
+/// the number of nodes.
 pub struct AgeQuorum {
     proportion: RR,
+    age_proportion: Option<RR>,
 }
 
 impl AgeQuorum {
     /// New structure. Default to requiring a quorum of the entire group.
     pub fn new() -> Self {
-        AgeQuorum { proportion: 1.0 }
+        AgeQuorum {
+            proportion: 1.0,
+            age_proportion: None,
+        }
+    }
+
+    // Proportion of age required
+    fn age_prop(&self) -> RR {
+        self.age_proportion.unwrap_or(self.proportion)
+    }
+
+    // Proportions of nodes, and of age, which are good and malicious
+    fn props(&self, group: &Group) -> ((RR, RR), (RR, RR)) {
+        let mut sum_age = 0;
+        let mut bad_age = 0;
+        for data in group.values() {
+            sum_age += data.age();
+            if data.is_malicious() {
+                bad_age += data.age();
+            }
+        }
+        let sum_age = sum_age as RR;
+        let good_age = sum_age - bad_age as RR;
+        (count_props(group.values()), (good_age / sum_age, bad_age as RR / sum_age))
+    }
+}
+
+impl Default for AgeQuorum {
+    fn default() -> Self {
+        AgeQuorum::new()
     }
 }
 
@@ -106,35 +261,293 @@ impl Quorum for AgeQuorum {
         self.proportion = prop;
     }
 
-    fn quorum_disrupted(&self, group: &HashMap<NodeName, NodeData>) -> bool {
-        let n_nodes = group.len() as RR;
-        let mut sum_age = 0;
-        let mut n_good = 0;
-        let mut good_age = 0;
-        for data in group.values() {
-            sum_age += data.age();
-            if !data.is_malicious() {
-                n_good += 1;
-                good_age += data.age();
-            }
+    fn set_age_proportion(&mut self, prop: RR) {
+        self.age_proportion = Some(prop);
+    }
+
+    fn quorum_disrupted(&self, group: &Group) -> Verdict {
+        let (counts, ages) = self.props(group);
+        Verdict::disruption(counts, Some(ages), self.proportion, self.age_prop())
+    }
+
+    fn quorum_compromised(&self, group: &Group) -> Verdict {
+        let (counts, ages) = self.props(group);
+        Verdict::compromise(counts, Some(ages), self.proportion, self.age_prop())
+    }
+}
+
+/// Weight given to a node's vote, as a function of its age
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AgeWeight {
+    /// The age itself (as used by `AgeQuorum`)
+    Linear,
+    /// 2^age, i.e. proportional to the number of churn events needed to reach the age
+    Exp,
+    /// The age, capped at the given maximum
+    Capped(u32),
+    /// Square root of the age
+    Sqrt,
+}
+
+impl AgeWeight {
+    /// Weight of a node with the given age
+    pub fn weight(&self, age: u32) -> RR {
+        match *self {
+            AgeWeight::Linear => age as RR,
+            AgeWeight::Exp => (2.0 as RR).powi(age as i32),
+            AgeWeight::Capped(max) => age.min(max) as RR,
+            AgeWeight::Sqrt => (age as RR).sqrt(),
         }
-        (n_good as RR) / n_nodes < self.proportion ||
-        (good_age as RR) / (sum_age as RR) < self.proportion
     }
+}
 
-    fn quorum_compromised(&self, group: &HashMap<NodeName, NodeData>) -> bool {
-        let n_nodes = group.len() as RR;
-        let mut sum_age = 0;
-        let mut n_bad = 0;
-        let mut bad_age = 0;
+/// Quorum which, like `AgeQuorum`, requires some proportion of nodes and of their summed
+/// weights, where each node's weight is a function of its age.
+pub struct WeightedAgeQuorum {
+    proportion: RR,
+    age_proportion: Option<RR>,
+    weight: AgeWeight,
+}
+
+impl WeightedAgeQuorum {
+    /// New structure, with the weight function. Default to requiring a quorum of the entire
+    /// group.
+    pub fn new(weight: AgeWeight) -> Self {
+        WeightedAgeQuorum {
+            proportion: 1.0,
+            age_proportion: None,
+            weight,
+        }
+    }
+
+    // Proportions of nodes, and of weight, which are good and malicious
+    fn props(&self, group: &Group) -> ((RR, RR), (RR, RR)) {
+        let mut sum_weight = 0.0;
+        let mut good_weight = 0.0;
+        let mut bad_weight = 0.0;
         for data in group.values() {
-            sum_age += data.age();
+            let w = self.weight.weight(data.age());
+            sum_weight += w;
             if data.is_malicious() {
-                n_bad += 1;
-                bad_age += data.age();
+                bad_weight += w;
+            } else {
+                good_weight += w;
             }
         }
-        (n_bad as RR) / n_nodes >= self.proportion &&
-        (bad_age as RR) / (sum_age as RR) >= self.proportion
+        (count_props(group.values()), (good_weight / sum_weight, bad_weight / sum_weight))
+    }
+
+    // Proportion of weight required
+    fn age_prop(&self) -> RR {
+        self.age_proportion.unwrap_or(self.proportion)
+    }
+}
+
+impl Quorum for WeightedAgeQuorum {
+    fn quorum_size(&self, _: NN) -> Option<NN> {
+        None
+    }
+
+    fn set_quorum_proportion(&mut self, prop: RR) {
+        self.proportion = prop;
+    }
+
+    fn set_age_proportion(&mut self, prop: RR) {
+        self.age_proportion = Some(prop);
+    }
+
+    fn quorum_disrupted(&self, group: &Group) -> Verdict {
+        let (counts, weights) = self.props(group);
+        Verdict::disruption(counts, Some(weights), self.proportion, self.age_prop())
+    }
+
+    fn quorum_compromised(&self, group: &Group) -> Verdict {
+        let (counts, weights) = self.props(group);
+        Verdict::compromise(counts, Some(weights), self.proportion, self.age_prop())
+    }
+}
+
+/// Quorum in which only the elders of a group vote: the `N` oldest nodes, with ties broken by
+/// name (lowest first). The quorum proportion applies to the elders only.
+pub struct ElderQuorum {
+    proportion: RR,
+    num_elders: usize,
+}
+
+impl ElderQuorum {
+    /// New structure, with the number of elders. Default to requiring a quorum of all elders.
+    pub fn new(num_elders: NN) -> Self {
+        ElderQuorum {
+            proportion: 1.0,
+            num_elders: num_elders as usize,
+        }
+    }
+
+    // Get the elders of a group
+    fn elders<'a>(&self, group: &'a Group) -> Vec<&'a NodeData> {
+        let mut nodes: Vec<_> = group.iter().collect();
+        nodes.sort_by(|x, y| y.1.age().cmp(&x.1.age()).then(x.0.cmp(y.0)));
+        nodes.into_iter().take(self.num_elders).map(|(_, data)| data).collect()
+    }
+}
+
+impl Quorum for ElderQuorum {
+    fn quorum_size(&self, k: NN) -> Option<NN> {
+        let n = k.min(self.num_elders as NN);
+        Some((n as RR * self.proportion).ceil() as NN)
+    }
+
+    fn set_quorum_proportion(&mut self, prop: RR) {
+        self.proportion = prop;
+    }
+
+    fn quorum_disrupted(&self, group: &Group) -> Verdict {
+        let counts = count_props(self.elders(group));
+        Verdict::disruption(counts, None, self.proportion, 0.0)
+    }
+
+    fn quorum_compromised(&self, group: &Group) -> Verdict {
+        let counts = count_props(self.elders(group));
+        Verdict::compromise(counts, None, self.proportion, 0.0)
+    }
+}
+
+/// Byzantine fault tolerant quorum, in which malicious members vote for both sides of any
+/// conflict (equivocate) while good members vote only once. Quorum is the given proportion of
+/// the group by number (`q` of `n` nodes, rounded up).
+///
+/// Safety is broken (the group is compromised) once two conflicting quorums can both form:
+/// with `f` malicious members this needs `2q - f <= n` and `f >= 1` (with a quorum of half the
+/// group or less, good nodes could form conflicting quorums, but never do), which happens before
+/// malicious nodes can form a quorum alone. Liveness is broken (the group is disrupted) when the
+/// good members alone cannot form a quorum. These are independent, so compromise does not imply
+/// disruption.
+pub struct BftQuorum {
+    proportion: RR,
+}
+
+impl BftQuorum {
+    /// New structure. Default to requiring a quorum of the entire group.
+    pub fn new() -> Self {
+        BftQuorum { proportion: 1.0 }
+    }
+
+    // Thresholds on the proportions of good and of malicious nodes for the group's quorum
+    fn thresholds(&self, group: &Group) -> (RR, RR) {
+        let n = group.len() as NN;
+        let q = self.quorum_size(n).expect("bft quorum size");
+        // conflicting quorums need 2q - n malicious nodes, and at least one to equivocate
+        let f = (2 * q).saturating_sub(n).max(1);
+        (q as RR / n as RR, f as RR / n as RR)
+    }
+}
+
+impl Default for BftQuorum {
+    fn default() -> Self {
+        BftQuorum::new()
+    }
+}
+
+impl Quorum for BftQuorum {
+    fn quorum_size(&self, k: NN) -> Option<NN> {
+        Some((k as RR * self.proportion).ceil() as NN)
+    }
+
+    fn set_quorum_proportion(&mut self, prop: RR) {
+        self.proportion = prop;
+    }
+
+    fn quorum_disrupted(&self, group: &Group) -> Verdict {
+        let (prop, _) = self.thresholds(group);
+        Verdict::disruption(count_props(group.values()), None, prop, 0.0)
+    }
+
+    fn quorum_compromised(&self, group: &Group) -> Verdict {
+        let (_, prop) = self.thresholds(group);
+        Verdict::compromise(count_props(group.values()), None, prop, 0.0)
+    }
+
+    fn compromise_implies_disruption(&self) -> bool {
+        false
+    }
+}
+
+// Group of nodes with given names, malicious flags and ages.
+#[cfg(test)]
+fn make_group(nodes: &[(NodeName, bool, u32)]) -> Group {
+    let mut group = Group::default();
+    for &(name, malicious, age) in nodes {
+        let mut data = NodeData::new(malicious);
+        for _ in 0..age {
+            data.incr_age();
+        }
+        group.insert(name, data);
+    }
+    group
+}
+
+#[test]
+fn test_age_quorum_separate_props() {
+    // Two good nodes of age 1; two malicious nodes of ages 2 and 4: half the nodes, 3/4 of age
+    let group = make_group(&[(0, false, 1), (1, false, 1), (2, true, 2), (3, true, 4)]);
+    let mut quorum = AgeQuorum::new();
+    quorum.set_quorum_proportion(0.5);
+    let verdict = quorum.quorum_compromised(&group);
+    assert!(verdict.failed);
+    assert_eq!((verdict.bad_count, verdict.bad_age), (0.5, Some(0.75)));
+    // count is met with no margin, age with 0.25
+    assert_eq!(verdict.binding, Binding::Count);
+    quorum.set_age_proportion(0.8);
+    let verdict = quorum.quorum_compromised(&group);
+    assert!(!verdict.failed);
+    assert_eq!(verdict.binding, Binding::Age);
+    let verdict = quorum.quorum_disrupted(&group);
+    assert!(verdict.failed);
+    assert_eq!((verdict.good_count, verdict.good_age), (0.5, Some(0.25)));
+    assert_eq!(verdict.binding, Binding::Age);
+}
+
+#[test]
+fn test_elder_quorum() {
+    // Malicious nodes 0 and 1 (age 1) and good nodes 2-4 (ages 3, 1 and 0)
+    let group = make_group(&[(0, true, 1), (1, true, 1), (2, false, 3), (3, false, 1),
+                             (4, false, 0)]);
+    let mut quorum = ElderQuorum::new(3);
+    quorum.set_quorum_proportion(0.6);
+    // Elders are 2, then 0 and 1 (tie with 3 broken by name): two thirds malicious
+    assert!(quorum.quorum_compromised(&group).failed);
+    assert_eq!(quorum.quorum_size(10), Some(2));
+    let mut quorum = ElderQuorum::new(4);
+    quorum.set_quorum_proportion(0.6);
+    assert!(!quorum.quorum_compromised(&group).failed);
+    assert!(quorum.quorum_disrupted(&group).failed);
+}
+
+#[test]
+fn test_age_weights() {
+    assert_eq!(AgeWeight::Linear.weight(3), 3.0);
+    assert_eq!(AgeWeight::Exp.weight(3), 8.0);
+    assert_eq!(AgeWeight::Capped(2).weight(3), 2.0);
+    assert_eq!(AgeWeight::Sqrt.weight(4), 2.0);
+}
+
+#[test]
+fn test_bft_quorum() {
+    let mut quorum = BftQuorum::new();
+    quorum.set_quorum_proportion(2.0 / 3.0);
+    // Groups of 9 with 2, 3 and 4 malicious nodes: quorum is 6
+    for (n_bad, compromised, disrupted) in [(2, false, false), (3, true, false), (4, true, true)] {
+        let nodes: Vec<_> = (0..9).map(|i| (i, i < n_bad, 0)).collect();
+        let group = make_group(&nodes);
+        assert_eq!(quorum.quorum_compromised(&group).failed, compromised);
+        assert_eq!(quorum.quorum_disrupted(&group).failed, disrupted);
+    }
+    // With half the group as quorum, two quorums can be disjoint, but safety is only broken once
+    // a malicious node can equivocate
+    quorum.set_quorum_proportion(0.5);
+    for (n_bad, compromised) in [(0, false), (1, true)] {
+        let nodes: Vec<_> = (0..8).map(|i| (i, i < n_bad, 0)).collect();
+        let group = make_group(&nodes);
+        assert_eq!(quorum.quorum_compromised(&group).failed, compromised);
     }
 }
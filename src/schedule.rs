@@ -0,0 +1,197 @@
+// Copyright 2016 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement, version 1.1.  This, along with the
+// Licenses can be found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Schedules of rates over the days of an attack
+//!
+//! A schedule gives a factor by which a base rate (e.g. the background join rate of good nodes)
+//! is multiplied on each day of the attack. Schedules are written:
+//!
+//! *   `const` — always 1 (the default)
+//! *   `linear:DAY=FACTOR,...` — piecewise-linear between the given points, and constant before
+//!     the first and after the last; repeating a day gives a step (e.g.
+//!     `linear:0=1,10=1,10=20,11=20,11=1` for a mass departure on day 10)
+//! *   `sine:A:P` — `1 + A sin(2π day / P)`, a cycle of `P` days with relative amplitude `A`
+//!     (note that the rate is only sampled once per step, so steps must be shorter than `P`)
+//! *   `file:PATH` — piecewise-linear between points read from a file, with one `day, factor`
+//!     line per point. Blank lines and lines starting with `#` are ignored.
+
+use std::f64::consts::PI;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+
+use RR;
+
+
+/// Factor applied to a rate over the days of an attack
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    /// Always 1
+    Constant,
+    /// Piecewise-linear between `(day, factor)` points, in order of day
+    Linear(Vec<(RR, RR)>),
+    /// Sinusoidal with relative amplitude and period (days)
+    Sine(RR, RR),
+    /// Piecewise-linear between points read from a file: path, and points
+    File(String, Vec<(RR, RR)>),
+}
+
+impl Schedule {
+    /// Parse a schedule as written on the command line (reading the file if one is named).
+    pub fn parse(s: &str) -> Result<Schedule, String> {
+        let err = || {
+            format!("schedule '{}': expected 'const', 'linear:DAY=FACTOR,...', 'sine:A:P' or \
+                     'file:PATH'",
+                    s)
+        };
+        if s == "const" {
+            Ok(Schedule::Constant)
+        } else if let Some(points) = s.strip_prefix("linear:") {
+            let points = points.split(',')
+                .map(|p| {
+                    let mut parts = p.splitn(2, '=');
+                    let day = parts.next().and_then(|x| x.trim().parse().ok());
+                    let factor = parts.next().and_then(|x| x.trim().parse().ok());
+                    match (day, factor) {
+                        (Some(day), Some(factor)) => Ok((day, factor)),
+                        _ => Err(err()),
+                    }
+                })
+                .collect::<Result<Vec<(RR, RR)>, String>>()?;
+            check_points(points).map(Schedule::Linear).map_err(|e| format!("{}: {}", s, e))
+        } else if let Some(params) = s.strip_prefix("sine:") {
+            let parts: Vec<RR> = params.split(':').filter_map(|x| x.parse().ok()).collect();
+            match parts[..] {
+                [amplitude, period] if (0.0..=1.0).contains(&amplitude) && period > 0.0 => {
+                    Ok(Schedule::Sine(amplitude, period))
+                }
+                _ => {
+                    Err(format!("schedule '{}': expected 'sine:A:P' with A between 0 and 1 \
+                                 and P positive",
+                                s))
+                }
+            }
+        } else if let Some(path) = s.strip_prefix("file:") {
+            Ok(Schedule::File(path.to_string(), read_points(path)?))
+        } else {
+            Err(err())
+        }
+    }
+
+    /// Factor to apply on `day` days into the attack (never negative).
+    pub fn factor(&self, day: RR) -> RR {
+        match *self {
+            Schedule::Constant => 1.0,
+            Schedule::Linear(ref points) |
+            Schedule::File(_, ref points) => interpolate(points, day),
+            Schedule::Sine(amplitude, period) => {
+                (1.0 + amplitude * (2.0 * PI * day / period).sin()).max(0.0)
+            }
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Schedule::Constant => write!(f, "const"),
+            Schedule::Linear(ref points) => {
+                let points: Vec<String> =
+                    points.iter().map(|p| format!("{}={}", p.0, p.1)).collect();
+                write!(f, "linear:{}", points.join(","))
+            }
+            Schedule::Sine(amplitude, period) => write!(f, "sine:{}:{}", amplitude, period),
+            Schedule::File(ref path, _) => write!(f, "file:{}", path),
+        }
+    }
+}
+
+/// Read a file of `day, value` lines (as used by file schedules), sorted by day.
+pub fn read_points(path: &str) -> Result<Vec<(RR, RR)>, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("unable to read {}: {}", path, e))?;
+    parse_points(&text).and_then(check_points).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Parse lines of "day, value", ignoring blank lines and comments.
+fn parse_points(text: &str) -> Result<Vec<(RR, RR)>, String> {
+    let mut points = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = || format!("line {}: expected 'day, value', found '{}'", i + 1, line);
+        let fields: Vec<&str> = line.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .collect();
+        if fields.len() != 2 {
+            return Err(err());
+        }
+        let day = fields[0].parse().map_err(|_| err())?;
+        let value = fields[1].parse().map_err(|_| err())?;
+        points.push((day, value));
+    }
+    Ok(points)
+}
+
+// Check points are non-empty, non-negative and sorted by day (keeping the order of equal days).
+fn check_points(mut points: Vec<(RR, RR)>) -> Result<Vec<(RR, RR)>, String> {
+    if points.is_empty() {
+        return Err("expected at least one point".to_string());
+    }
+    if points.iter().any(|p| !(p.0 >= 0.0 && p.1 >= 0.0)) {
+        return Err("days and values must not be negative".to_string());
+    }
+    points.sort_by(|x, y| x.0.partial_cmp(&y.0).expect("comparable days"));
+    Ok(points)
+}
+
+// Linear interpolation between sorted points; constant outside them.
+fn interpolate(points: &[(RR, RR)], day: RR) -> RR {
+    let i = points.iter().take_while(|p| p.0 <= day).count();
+    if i == 0 {
+        return points[0].1;
+    }
+    let (d0, v0) = points[i - 1];
+    match points.get(i) {
+        Some(&(d1, v1)) => v0 + (v1 - v0) * (day - d0) / (d1 - d0),
+        None => v0,
+    }
+}
+
+#[test]
+fn test_schedule() {
+    assert_eq!(Schedule::parse("const").expect("const").factor(5.0), 1.0);
+    let linear = Schedule::parse("linear:10=1,20=3,20=0").expect("linear");
+    assert_eq!(linear.factor(0.0), 1.0);
+    assert_eq!(linear.factor(15.0), 2.0);
+    assert_eq!(linear.factor(20.0), 0.0);
+    assert_eq!(linear.factor(100.0), 0.0);
+    assert_eq!(linear.to_string(), "linear:10=1,20=3,20=0");
+    let sine = Schedule::parse("sine:0.5:4").expect("sine");
+    assert!((sine.factor(1.0) - 1.5).abs() < 1e-12);
+    assert!((sine.factor(3.0) - 0.5).abs() < 1e-12);
+    assert!(Schedule::parse("sine:2:4").is_err());
+    assert!(Schedule::parse("linear:1=x").is_err());
+    assert!(Schedule::parse("linear:1=-1").is_err());
+    assert_eq!(parse_points("# day, factor\n0, 1\n5 2\n").expect("points"),
+               vec![(0.0, 1.0), (5.0, 2.0)]);
+    assert!(parse_points("1, 2, 3").is_err());
+}
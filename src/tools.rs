@@ -15,40 +15,449 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-/// Drivers of the simulations / calculations
+//! Drivers of the simulations / calculations
 
+use std::cmp::{min, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
 
 use rayon::prelude::*;
 
-use {NN, RR, ToolArgs};
-use quorum::{Quorum, SimpleQuorum};
+use {NN, RR, ToolArgs, ArgsError};
+use quorum::{Binding, Quorum, SimpleQuorum};
 use attack::{AttackStrategy, UntargettedAttack};
-use prob::{prob_disruption, prob_compromise};
-use net::{Network, NoAddRestriction, RestrictOnePerAge};
+use prob::{prob_disruption, prob_compromise, make_rng, sub_seed, wilson_interval, std_error,
+           Z_95};
+use net::{Network, NetStats, NoAddRestriction, RestrictOnePerAge};
+
+
+/// Numbers of trials in which each quorum condition decided a verdict (see `quorum::Binding`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BindingCounts {
+    /// Decided by the count of nodes
+    pub count: NN,
+    /// Decided by age
+    pub age: NN,
+    /// Decided by both
+    pub both: NN,
+}
+
+impl BindingCounts {
+    fn add(&mut self, binding: Option<Binding>) {
+        match binding {
+            Some(Binding::Count) => self.count += 1,
+            Some(Binding::Age) => self.age += 1,
+            Some(Binding::Both) => self.both += 1,
+            None => {}
+        }
+    }
+
+    fn merge(&mut self, other: BindingCounts) {
+        self.count += other.count;
+        self.age += other.age;
+        self.both += other.both;
+    }
+}
+
+/// Outcomes of repeated simulations.
+///
+/// For each of disruption and compromise, the time (in days since the start of the attack) at
+/// which it first occurred is kept for every trial where it occurred.
+#[derive(Clone, Default)]
+pub struct Trials {
+    /// Number of simulations run
+    pub n: NN,
+    disrupt_days: Vec<RR>,
+    compromise_days: Vec<RR>,
+    compromise_spent: Vec<RR>,
+    disrupt_binding: BindingCounts,
+    compromise_binding: BindingCounts,
+    blocked_binding: BindingCounts,
+}
+
+impl Trials {
+    /// Outcome of a single simulation: first time of disruption and of compromise (if any).
+    pub fn single(disrupt_day: Option<RR>, compromise_day: Option<RR>) -> Self {
+        Trials {
+            n: 1,
+            disrupt_days: disrupt_day.into_iter().collect(),
+            compromise_days: compromise_day.into_iter().collect(),
+            compromise_spent: vec![],
+            disrupt_binding: BindingCounts::default(),
+            compromise_binding: BindingCounts::default(),
+            blocked_binding: BindingCounts::default(),
+        }
+    }
+
+    /// Record the work spent by the attacker (node-days) by the time of compromise, for a
+    /// single compromised simulation.
+    pub fn with_spent(mut self, spent: RR) -> Self {
+        self.compromise_spent.push(spent);
+        self
+    }
+
+    /// Record which quorum condition decided disruption and compromise, for a single
+    /// simulation. Where compromise did not occur, `blocked` gives the condition not met in the
+    /// group closest to compromise.
+    pub fn with_bindings(mut self,
+                         disrupt: Option<Binding>,
+                         compromise: Option<Binding>,
+                         blocked: Option<Binding>)
+                         -> Self {
+        self.disrupt_binding.add(disrupt);
+        self.compromise_binding.add(compromise);
+        self.blocked_binding.add(blocked);
+        self
+    }
+
+    /// Combine outcomes from two sets of trials
+    pub fn merge(mut self, other: Trials) -> Trials {
+        self.n += other.n;
+        self.disrupt_days.extend(other.disrupt_days);
+        self.compromise_days.extend(other.compromise_days);
+        self.compromise_spent.extend(other.compromise_spent);
+        self.disrupt_binding.merge(other.disrupt_binding);
+        self.compromise_binding.merge(other.compromise_binding);
+        self.blocked_binding.merge(other.blocked_binding);
+        self
+    }
+
+    /// Number of simulations where disruption occurred. This includes all those with compromise
+    /// only if the quorum's `compromise_implies_disruption()` is true.
+    pub fn disrupted(&self) -> NN {
+        self.disrupt_days.len() as NN
+    }
+
+    /// Number of simulations where compromise occurred
+    pub fn compromised(&self) -> NN {
+        self.compromise_days.len() as NN
+    }
+
+    /// Times to disruption, in increasing order, of trials where disruption occurred
+    pub fn disrupt_days(&self) -> &[RR] {
+        &self.disrupt_days
+    }
+
+    /// Times to compromise, in increasing order, of trials where compromise occurred
+    pub fn compromise_days(&self) -> &[RR] {
+        &self.compromise_days
+    }
+
+    /// Work spent by the attacker (node-days) by the time of compromise, in increasing order,
+    /// of trials where compromise occurred
+    pub fn compromise_spent(&self) -> &[RR] {
+        &self.compromise_spent
+    }
+
+    /// Conditions deciding disruption, over trials where disruption occurred
+    pub fn disrupt_binding(&self) -> BindingCounts {
+        self.disrupt_binding
+    }
+
+    /// Conditions last met at compromise, over trials where compromise occurred
+    pub fn compromise_binding(&self) -> BindingCounts {
+        self.compromise_binding
+    }
+
+    /// Conditions preventing compromise at the end, over trials where compromise did not occur
+    pub fn blocked_binding(&self) -> BindingCounts {
+        self.blocked_binding
+    }
+
+    // Sort times; this also makes results independent of the order trials were merged in.
+    fn sort(&mut self) {
+        self.disrupt_days.sort_by(|x, y| x.partial_cmp(y).expect("comparable times"));
+        self.compromise_days.sort_by(|x, y| x.partial_cmp(y).expect("comparable times"));
+        self.compromise_spent.sort_by(|x, y| x.partial_cmp(y).expect("comparable times"));
+    }
+}
+
+// Mean of times (`None` if empty)
+fn mean_days(days: &[RR]) -> Option<RR> {
+    if days.is_empty() {
+        None
+    } else {
+        Some(days.iter().sum::<RR>() / days.len() as RR)
+    }
+}
+
+// Time by which half of `n` trials have had the event, given sorted times of trials which had
+// it (`None` if this didn't happen in half the trials).
+fn median_days(days: &[RR], n: NN) -> Option<RR> {
+    let half = (n as usize).div_ceil(2);
+    if half == 0 || days.len() < half {
+        None
+    } else {
+        Some(days[half - 1])
+    }
+}
+
+// Empirical CDF over all `n` trials (not just those in which the event happened): a list of
+// `(day, proportion of trials where the event happened by this day)`.
+fn ecdf(days: &[RR], n: NN) -> Vec<(RR, RR)> {
+    let mut cdf: Vec<(RR, RR)> = vec![];
+    for (i, day) in days.iter().enumerate() {
+        let p = (i + 1) as RR / n as RR;
+        match cdf.last_mut() {
+            Some(last) if last.0 == *day => last.1 = p,
+            _ => cdf.push((*day, p)),
+        }
+    }
+    cdf
+}
+
+#[test]
+fn test_time_stats() {
+    let days = [1.0, 2.0, 2.0, 5.0];
+    assert_eq!(mean_days(&days), Some(2.5));
+    assert_eq!(median_days(&days, 4), Some(2.0));
+    assert_eq!(median_days(&days, 7), Some(5.0));
+    assert_eq!(median_days(&days, 9), None);
+    assert_eq!(ecdf(&days, 8), vec![(1.0, 0.125), (2.0, 0.375), (5.0, 0.5)]);
+}
 
+#[test]
+fn test_binding_counts() {
+    let trials = Trials::single(Some(1.0), Some(1.0))
+        .with_bindings(Some(Binding::Age), Some(Binding::Count), None)
+        .merge(Trials::single(Some(2.0), None)
+            .with_bindings(Some(Binding::Age), None, Some(Binding::Both)))
+        .merge(Trials::single(None, None).with_bindings(None, None, Some(Binding::Count)));
+    assert_eq!(trials.n, 3);
+    assert_eq!(trials.disrupt_binding(),
+               BindingCounts {
+                   count: 0,
+                   age: 2,
+                   both: 0,
+               });
+    assert_eq!(trials.compromise_binding(),
+               BindingCounts {
+                   count: 1,
+                   age: 0,
+                   both: 0,
+               });
+    assert_eq!(trials.blocked_binding(),
+               BindingCounts {
+                   count: 1,
+                   age: 0,
+                   both: 1,
+               });
+}
 
-/// First value is probability of disruption, second is probability of compromise.
+/// Controls how many times a simulation is repeated.
 #[derive(Clone, Copy)]
-pub struct SimResult(RR, RR);
+pub struct Repetitions {
+    /// Number of repetitions, or, when `precision` is set, the number run in each batch.
+    pub batch: u32,
+    /// If set, batches are run until the confidence intervals of both probabilities are no
+    /// wider than this (or a limit below is reached).
+    pub precision: Option<RR>,
+    /// Maximum number of repetitions when `precision` is set.
+    pub max_trials: NN,
+    /// Maximum time (seconds) spent on one parameter set when `precision` is set. Note that
+    /// using this makes results depend on the speed of the machine.
+    pub max_time: Option<RR>,
+}
+
+impl Repetitions {
+    /// Fixed number of repetitions
+    pub fn fixed(n: u32) -> Self {
+        Repetitions {
+            batch: n,
+            precision: None,
+            max_trials: n as NN,
+            max_time: None,
+        }
+    }
+
+    /// Check the settings can be used: batches and the maximum number of trials must not be
+    /// zero and, with a precision, the precision must be in the range (0, 1] and any maximum
+    /// time must be non-negative.
+    pub fn check(&self) -> Result<(), ArgsError> {
+        if self.batch == 0 {
+            return Err(ArgsError::EmptyBatch);
+        }
+        if self.max_trials == 0 {
+            return Err(ArgsError::NoTrials);
+        }
+        let precision = match self.precision {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if !(precision > 0.0 && precision <= 1.0) {
+            return Err(ArgsError::Precision(precision));
+        }
+        match self.max_time {
+            Some(t) if !(t.is_finite() && t >= 0.0) => Err(ArgsError::MaxTime(t)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether more trials are wanted, given those done so far and the time taken.
+    pub fn want_more(&self, result: &SimResult, elapsed: Duration) -> bool {
+        let trials = match result.trials() {
+            Some(t) => t,
+            None => return false,
+        };
+        let precision = match self.precision {
+            Some(p) => p,
+            None => return trials.n < self.batch as NN,
+        };
+        if trials.n >= self.max_trials ||
+           self.max_time.is_some_and(|t| elapsed.as_secs_f64() >= t) {
+            return false;
+        }
+        let width = |ci: Option<(RR, RR)>| ci.map_or(1.0, |ci| ci.1 - ci.0);
+        width(result.ci_disrupt()) > precision || width(result.ci_compromise()) > precision
+    }
+}
+
+/// Probabilities of disruption and of compromise.
+///
+/// Where the probabilities were estimated by repeating a simulation, the counts are also kept,
+/// and can be used to find the precision of the estimates.
+#[derive(Clone)]
+pub struct SimResult {
+    p_disrupt: RR,
+    p_compromise: RR,
+    trials: Option<Trials>,
+}
+
 impl SimResult {
+    /// Result from calculated probabilities
+    pub fn from_probs(p_disrupt: RR, p_compromise: RR) -> Self {
+        SimResult {
+            p_disrupt,
+            p_compromise,
+            trials: None,
+        }
+    }
+
+    /// Result from outcomes of trials
+    pub fn from_trials(mut trials: Trials) -> Self {
+        trials.sort();
+        let n = trials.n as RR;
+        SimResult {
+            p_disrupt: trials.disrupted() as RR / n,
+            p_compromise: trials.compromised() as RR / n,
+            trials: Some(trials),
+        }
+    }
+
     pub fn p_disrupt(&self) -> RR {
-        self.0
+        self.p_disrupt
     }
     pub fn p_compromise(&self) -> RR {
-        self.1
+        self.p_compromise
+    }
+
+    /// Get outcomes of trials, if the result came from simulations
+    pub fn trials(&self) -> Option<&Trials> {
+        self.trials.as_ref()
+    }
+
+    /// Standard error of `p_disrupt()`, if estimated from trials
+    pub fn se_disrupt(&self) -> Option<RR> {
+        self.trials().map(|t| std_error(t.disrupted(), t.n))
+    }
+    /// Standard error of `p_compromise()`, if estimated from trials
+    pub fn se_compromise(&self) -> Option<RR> {
+        self.trials().map(|t| std_error(t.compromised(), t.n))
+    }
+
+    /// 95% (Wilson) confidence interval of `p_disrupt()`, if estimated from trials
+    pub fn ci_disrupt(&self) -> Option<(RR, RR)> {
+        self.trials().map(|t| wilson_interval(t.disrupted(), t.n, Z_95))
+    }
+    /// 95% (Wilson) confidence interval of `p_compromise()`, if estimated from trials
+    pub fn ci_compromise(&self) -> Option<(RR, RR)> {
+        self.trials().map(|t| wilson_interval(t.compromised(), t.n, Z_95))
+    }
+
+    /// Mean time to disruption (days), over the trials where disruption occurred
+    pub fn mean_days_to_disrupt(&self) -> Option<RR> {
+        self.trials().and_then(|t| mean_days(t.disrupt_days()))
+    }
+    /// Median time to disruption (days) over all trials: `None` unless disruption occurred in
+    /// at least half the trials
+    pub fn median_days_to_disrupt(&self) -> Option<RR> {
+        self.trials().and_then(|t| median_days(t.disrupt_days(), t.n))
+    }
+
+    /// Mean time to compromise (days), over the trials where compromise occurred.
+    ///
+    /// Note that this is conditional on compromise within the time limit (`max_days`).
+    pub fn mean_days_to_compromise(&self) -> Option<RR> {
+        self.trials().and_then(|t| mean_days(t.compromise_days()))
+    }
+    /// Median time to compromise (days) over all trials: `None` unless compromise occurred in
+    /// at least half the trials
+    pub fn median_days_to_compromise(&self) -> Option<RR> {
+        self.trials().and_then(|t| median_days(t.compromise_days(), t.n))
+    }
+
+    /// Mean work spent by the attacker before compromise (node-days), over the trials where
+    /// compromise occurred
+    pub fn mean_spent_to_compromise(&self) -> Option<RR> {
+        self.trials().and_then(|t| mean_days(t.compromise_spent()))
+    }
+
+    /// Empirical CDF of time to compromise: a list of `(day, p)` where `p` is the proportion of
+    /// all trials compromised by that day. Empty unless estimated from trials.
+    pub fn compromise_cdf(&self) -> Vec<(RR, RR)> {
+        self.trials().map_or(vec![], |t| ecdf(t.compromise_days(), t.n))
     }
 }
 
 
+/// Phase of a simulation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    /// Creation of the initial network of good nodes
+    Init,
+    /// The attack
+    Attack,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Init => "init",
+            Phase::Attack => "attack",
+        }
+    }
+}
+
+/// State of the network after one step of a simulation.
+pub struct StepRecord {
+    pub phase: Phase,
+    /// Step number, counting from the start of the simulation
+    pub step: NN,
+    /// Time since the start of the attack (days); `None` before the attack
+    pub day: Option<RR>,
+    pub stats: NetStats,
+}
+
+/// Observer called after each step of a traced simulation.
+pub type StepObserver<'a> = &'a mut dyn FnMut(&StepRecord);
+
 pub trait Tool {
     /// Print a message about the computation (does not include parameters).
     fn print_message(&self);
 
     /// Calculate the probability of compromise (range: 0 to 1).
     ///
-    /// `repetitions` is how many times to repeat the simulation; this is only applicable to the
-    /// full sim.
-    fn calc_p_compromise(&self, repetitions: u32) -> SimResult;
+    /// `repetitions` controls how many times to repeat the simulation; this is only applicable to
+    /// the full sim.
+    fn calc_p_compromise(&self, repetitions: &Repetitions) -> SimResult;
+
+    /// Run a single simulation (the first repetition), passing a record of the network state to
+    /// `observer` after each step.
+    ///
+    /// Returns `None` if the tool does not support this (default implementation).
+    fn trace_sim(&self, _observer: StepObserver) -> Option<SimResult> {
+        None
+    }
 }
 
 
@@ -63,8 +472,8 @@ impl<'a> DirectCalcTool<'a> {
     pub fn new(args: &'a ToolArgs) -> Self {
         let quorum = SimpleQuorum::from(args.quorum_prop);
         DirectCalcTool {
-            args: args,
-            quorum: quorum,
+            args,
+            quorum,
         }
     }
 }
@@ -81,7 +490,7 @@ impl<'a> Tool for DirectCalcTool<'a> {
         }
     }
 
-    fn calc_p_compromise(&self, _: u32) -> SimResult {
+    fn calc_p_compromise(&self, _: &Repetitions) -> SimResult {
         let k = self.args.min_group_size;
         let q = self.quorum.quorum_size(k).expect("simple quorum size");
         let n = self.args.num_initial + self.args.num_attacking;
@@ -97,8 +506,8 @@ impl<'a> Tool for DirectCalcTool<'a> {
                pc);
 
         let n_groups = (n as RR) / (self.args.min_group_size as RR);
-        SimResult(1.0 - (1.0 - pd).powf(n_groups),
-                  1.0 - (1.0 - pc).powf(n_groups))
+        SimResult::from_probs(1.0 - (1.0 - pd).powf(n_groups),
+                              1.0 - (1.0 - pc).powf(n_groups))
     }
 }
 
@@ -118,8 +527,8 @@ impl<'a> SimStructureTool<'a> {
     pub fn new(args: &'a ToolArgs) -> Self {
         let quorum = SimpleQuorum::from(args.quorum_prop);
         SimStructureTool {
-            args: args,
-            quorum: quorum,
+            args,
+            quorum,
         }
     }
 }
@@ -136,22 +545,22 @@ impl<'a> Tool for SimStructureTool<'a> {
         }
     }
 
-    fn calc_p_compromise(&self, _: u32) -> SimResult {
+    fn calc_p_compromise(&self, _: &Repetitions) -> SimResult {
         // We need an "attack" strategy, though we only support one here
         let mut attack = UntargettedAttack {};
 
         // Create a network of good nodes (this tool assumes all nodes are good in the sim then
         // assumes some are bad in subsequent calculations).
-        let mut net = Network::new(self.args.min_group_size as usize);
+        let mut net = Network::new(self.args.min_group_size as usize, make_rng(self.args.seed));
         // Yes, *attacking* nodes are *good* for this network initialisation!
         net.add_avail(self.args.num_initial + self.args.num_attacking, 0);
         while net.has_avail() {
-            net.do_step::<NoAddRestriction>(&self.args, &mut attack);
+            net.do_step::<NoAddRestriction>(self.args, &mut attack);
         }
         // The above got all available nodes ready for insert, but the last step will have left
         // some pending insert, so do one more step. Note that we can't wait until the queues are
         // empty because background-leaving may result in a constant churn.
-        net.do_step::<NoAddRestriction>(&self.args, &mut attack);
+        net.do_step::<NoAddRestriction>(self.args, &mut attack);
 
         // This isn't quite right, since one group not compromised does
         // tell you _something_ about the distribution of malicious nodes,
@@ -159,7 +568,7 @@ impl<'a> Tool for SimStructureTool<'a> {
         // of malicious nodes it should be close.
         let mut p_no_disruption = 1.0;
         let mut p_no_compromise = 1.0;
-        for (_, group) in net.groups() {
+        for group in net.groups().values() {
             let k = group.len() as NN;
             let q = self.quorum.quorum_size(k).expect("simple quorum size");
             let n = self.args.num_initial + self.args.num_attacking;
@@ -168,7 +577,7 @@ impl<'a> Tool for SimStructureTool<'a> {
             p_no_disruption *= 1.0 - pd;
             p_no_compromise *= 1.0 - pc;
         }
-        SimResult(1.0 - p_no_disruption, 1.0 - p_no_compromise)
+        SimResult::from_probs(1.0 - p_no_disruption, 1.0 - p_no_compromise)
     }
 }
 
@@ -180,63 +589,139 @@ pub struct FullSimTool<'a, Q: Quorum, A: AttackStrategy + Clone> {
     args: &'a ToolArgs,
     quorum: Q,
     attack: A,
+    reps_done: Option<&'a AtomicUsize>,
 }
 
 impl<'a, Q: Quorum, A: AttackStrategy + Clone> FullSimTool<'a, Q, A> {
     pub fn new(args: &'a ToolArgs, mut quorum: Q, strategy: A) -> Self {
         quorum.set_quorum_proportion(args.quorum_prop);
+        if let Some(prop) = args.age_quorum_prop {
+            quorum.set_age_proportion(prop);
+        }
         FullSimTool {
-            args: args,
-            quorum: quorum,
+            args,
+            quorum,
             attack: strategy,
+            reps_done: None,
         }
     }
 
-    // Run a simulation. Result has either 0 or 1 in each field, `(any_disruption, any_compromise)`.
-    fn run_sim(&self) -> SimResult {
+    /// Increment `counter` after each repetition (e.g. to report progress from another thread).
+    pub fn count_reps(mut self, counter: &'a AtomicUsize) -> Self {
+        self.reps_done = Some(counter);
+        self
+    }
+
+    // Run a simulation. Result is a single trial.
+    //
+    // `rep` is the repetition number, used to select the random number stream. If an `observer`
+    // is given, it is passed the state of the network after each step.
+    fn run_sim(&self, rep: NN, mut observer: Option<StepObserver>) -> Trials {
         info!("Starting sim");
         let mut attack = self.attack.clone();
+        let rng = make_rng(sub_seed(self.args.seed, rep));
+        let mut n_steps = 0;
+        let mut observe = |phase, day, net: &Network| {
+            if let Some(ref mut observer) = observer {
+                observer(&StepRecord {
+                    phase,
+                    step: n_steps,
+                    day,
+                    stats: net.stats(),
+                });
+            }
+            n_steps += 1;
+        };
 
         // 1. Create an initial network of good nodes.
-        let mut net = Network::new(self.args.min_group_size as usize);
+        let mut net = Network::new(self.args.min_group_size as usize, rng);
         net.add_avail(self.args.num_initial, 0);
         while net.has_avail() {
-            net.do_step::<RestrictOnePerAge>(&self.args, &mut attack);
+            net.do_step::<RestrictOnePerAge>(self.args, &mut attack);
+            observe(Phase::Init, None, &net);
         }
         // The above got all available nodes ready for insert, but the last step will have left
         // some pending insert, so do one more step. Note that we can't wait until the queues are
         // empty because background-leaving may result in a constant churn.
-        net.do_step::<RestrictOnePerAge>(&self.args, &mut attack);
+        net.do_step::<RestrictOnePerAge>(self.args, &mut attack);
+        observe(Phase::Init, None, &net);
 
         // 2. Start attack
-        // In this model, malicious nodes are added once while good nodes can be added
-        // continuously.
-        net.add_avail(0, self.args.num_attacking);
+        // Malicious nodes arrive according to the arrival profile (by default all at once),
+        // while good nodes can be added continuously.
+        let mut n_arrived = 0;
         let mut to_add_good = 0.0;
 
-        let mut disruption = false;
+        let mut disrupt_day = None;
+        let mut disrupt_binding = None;
+        // day of compromise, work spent by the attacker by then, and the condition last met
+        let mut compromise = None;
+
+        for step in 0..self.args.max_steps {
+            // Arrivals and rates are sampled at the start of the step
+            let start_day = step as RR * self.args.step_len;
+            let arrived = self.args.arrival().arrived(self.args.num_attacking, start_day);
+            net.add_avail(0, arrived - n_arrived);
+            n_arrived = arrived;
 
-        for _ in 0..self.args.max_steps {
-            to_add_good += self.args.add_rate_good;
+            to_add_good += self.args.add_rate_good * self.args.add_schedule().factor(start_day);
+            net.set_leave_factor(self.args.leave_schedule().factor(start_day));
             let n_new = to_add_good.floor();
             net.add_avail(n_new as NN, 0);
             to_add_good -= n_new;
 
-            net.do_step::<RestrictOnePerAge>(&self.args, &mut attack);
-
-            // Finally, we check if disruption or compromise occurred:
-            for (_, ref group) in net.groups() {
-                if self.quorum.quorum_compromised(group) {
-                    // Compromise implies disruption!
-                    return SimResult(1.0, 1.0);
-                } else if self.quorum.quorum_disrupted(group) {
-                    disruption = true;
+            net.do_step::<RestrictOnePerAge>(self.args, &mut attack);
+
+            // Finally, we check if disruption or compromise occurred (by the end of the step):
+            let day = (step + 1) as RR * self.args.step_len;
+            observe(Phase::Attack, Some(day), &net);
+            for group in net.groups().values() {
+                if compromise.is_none() {
+                    let verdict = self.quorum.quorum_compromised(group);
+                    if verdict.failed {
+                        compromise = Some((day, net.attack_spent(), verdict.binding));
+                        if self.quorum.compromise_implies_disruption() && disrupt_day.is_none() {
+                            // record the condition failing disruption, not the one met last
+                            disrupt_day = Some(day);
+                            disrupt_binding = Some(self.quorum.quorum_disrupted(group).binding);
+                        }
+                    }
+                }
+                if disrupt_day.is_none() {
+                    let verdict = self.quorum.quorum_disrupted(group);
+                    if verdict.failed {
+                        disrupt_day = Some(day);
+                        disrupt_binding = Some(verdict.binding);
+                    }
                 }
             }
+            // Stop once both have occurred
+            if disrupt_day.is_some() && compromise.is_some() {
+                break;
+            }
         }
 
-        // If we didn't return already, no compromise occurred, but disruption may have
-        SimResult(if disruption { 1.0 } else { 0.0 }, 0.0)
+        match compromise {
+            Some((day, spent, binding)) => {
+                Trials::single(disrupt_day, Some(day))
+                    .with_spent(spent)
+                    .with_bindings(disrupt_binding, Some(binding), None)
+            }
+            None => {
+                // Find what blocks compromise of the group with most malicious nodes (and then
+                // most malicious age)
+                let closest = net.groups()
+                    .values()
+                    .map(|group| self.quorum.quorum_compromised(group))
+                    .max_by(|x, y| {
+                        (x.bad_count, x.bad_age)
+                            .partial_cmp(&(y.bad_count, y.bad_age))
+                            .unwrap_or(Ordering::Equal)
+                    });
+                Trials::single(disrupt_day, None)
+                    .with_bindings(disrupt_binding, None, closest.map(|v| v.binding))
+            }
+        }
     }
 }
 
@@ -254,14 +739,37 @@ impl<'a, Q: Quorum, A: AttackStrategy + Clone> Tool for FullSimTool<'a, Q, A>
         }
     }
 
-    fn calc_p_compromise(&self, repetitions: u32) -> SimResult {
-        let result = (0..repetitions)
-            .into_par_iter()
-            .map(|_| self.run_sim())
-            .reduce(|| SimResult(0.0, 0.0),
-                    |v1, v2| SimResult(v1.0 + v2.0, v1.1 + v2.1));
+    fn calc_p_compromise(&self, repetitions: &Repetitions) -> SimResult {
+        // Repetitions are run in batches, each in parallel. Since repetition numbers select the
+        // random number streams, the result only depends on the number of batches run.
+        let start = Instant::now();
+        let mut result = SimResult::from_trials(Trials::default());
+        loop {
+            let trials = result.trials.take().expect("have trials");
+            let first = trials.n;
+            let batch = if repetitions.precision.is_some() {
+                min(repetitions.batch as NN, repetitions.max_trials - first)
+            } else {
+                repetitions.batch as NN
+            };
+            let batch_trials = (first as usize..(first + batch) as usize)
+                .into_par_iter()
+                .map(|rep| {
+                    let trials = self.run_sim(rep as NN, None);
+                    if let Some(counter) = self.reps_done {
+                        counter.fetch_add(1, AtomicOrdering::Relaxed);
+                    }
+                    trials
+                })
+                .reduce(Trials::default, Trials::merge);
+            result = SimResult::from_trials(trials.merge(batch_trials));
+            if !repetitions.want_more(&result, start.elapsed()) {
+                return result;
+            }
+        }
+    }
 
-        let denom = repetitions as RR;
-        SimResult(result.0 / denom, result.1 / denom)
+    fn trace_sim(&self, observer: StepObserver) -> Option<SimResult> {
+        Some(SimResult::from_trials(self.run_sim(0, Some(observer))))
     }
 }